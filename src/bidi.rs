@@ -0,0 +1,106 @@
+//! A minimal client for `WebDriver BiDi`, used for the few commands which
+//! classic `WebDriver` has no equivalent of.
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+/// A connection to a browser's `WebDriver BiDi` socket.
+pub struct BiDi {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl BiDi {
+    /// Connect to the `BiDi` socket of a session at `url`.
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let (socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| format!("Failed to connect to {url}: {e}"))?;
+        Ok(Self { socket, next_id: 0 })
+    }
+
+    /// Send the command `method` and wait for its result. Any events received
+    /// in the meantime are ignored.
+    pub async fn command(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let command = json!({ "id": id, "method": method, "params": params });
+        self.socket
+            .send(Message::text(command.to_string()))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        while let Some(message) = self.socket.next().await {
+            if let Message::Text(text) = message.map_err(|e| e.to_string())? {
+                if let Some(result) = parse_response(id, &text) {
+                    return result;
+                }
+            }
+        }
+        Err(format!("The connection closed before {method} finished."))
+    }
+}
+
+/// Read the outcome of the command with the id `id` from a message sent by
+/// the browser, or `None` if the message is about something else, such as an
+/// event. Errors without an id, for commands the browser couldn't read, are
+/// taken to be for this command, as only one is sent at a time.
+fn parse_response(id: u64, message: &str) -> Option<Result<Value, String>> {
+    let message: Value = serde_json::from_str(message).ok()?;
+    let is_error = message["type"] == "error";
+    if message["id"].as_u64() != Some(id) && !(is_error && message["id"].is_null()) {
+        return None;
+    }
+    if is_error {
+        Some(Err(format!(
+            "{}: {}",
+            message["error"].as_str().unwrap_or("unknown error"),
+            message["message"].as_str().unwrap_or_default()
+        )))
+    } else {
+        Some(Ok(message["result"].clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse_response;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(2, r#"{"type":"success","id":2,"result":{"script":"abc"}}"#),
+            Some(Ok(json!({ "script": "abc" })))
+        );
+        assert_eq!(
+            parse_response(3, r#"{"type":"success","id":2,"result":{}}"#),
+            None
+        );
+        assert_eq!(
+            parse_response(
+                2,
+                r#"{"type":"event","method":"log.entryAdded","params":{}}"#
+            ),
+            None
+        );
+        assert_eq!(
+            parse_response(
+                2,
+                r#"{"type":"error","id":2,"error":"no such script","message":"Not found"}"#
+            ),
+            Some(Err("no such script: Not found".to_string()))
+        );
+        assert_eq!(
+            parse_response(
+                2,
+                r#"{"type":"error","id":null,"error":"invalid argument","message":""}"#
+            ),
+            Some(Err("invalid argument: ".to_string()))
+        );
+        assert_eq!(parse_response(2, "not json"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use serde_json::json;
+use thirtyfour::{
+    common::command::FormatRequestData, error::WebDriverResult, session::handle::SessionHandle,
+    ElementId, RequestData, RequestMethod, SessionId, WebElement,
+};
+
+/// `WebDriver` commands which aren't provided by thirtyfour.
+#[derive(Debug)]
+pub enum BrowserCommand {
+    /// Get the role of an element as computed by the browser.
+    GetComputedRole(ElementId),
+    /// Get the accessible name of an element as computed by the browser.
+    GetComputedLabel(ElementId),
+    /// Upload a file, as a base64 encoded zip archive, to a remote driver.
+    /// This is supported by Selenium Grid and compatible servers.
+    UploadFile(String),
+}
+
+impl FormatRequestData for BrowserCommand {
+    fn format_request(&self, session_id: &SessionId) -> RequestData {
+        match self {
+            Self::GetComputedRole(element_id) => RequestData::new(
+                RequestMethod::Get,
+                format!("/session/{session_id}/element/{element_id}/computedrole"),
+            ),
+            Self::GetComputedLabel(element_id) => RequestData::new(
+                RequestMethod::Get,
+                format!("/session/{session_id}/element/{element_id}/computedlabel"),
+            ),
+            Self::UploadFile(zip_base64) => RequestData::new(
+                RequestMethod::Post,
+                format!("/session/{session_id}/se/file"),
+            )
+            .add_body(json!({ "file": zip_base64 })),
+        }
+    }
+}
+
+/// Get the role of an element as computed by the browser.
+pub async fn computed_role(elem: &WebElement) -> WebDriverResult<String> {
+    elem.handle
+        .cmd(BrowserCommand::GetComputedRole(elem.element_id()))
+        .await?
+        .value()
+}
+
+/// Get the accessible name of an element as computed by the browser.
+pub async fn computed_label(elem: &WebElement) -> WebDriverResult<String> {
+    elem.handle
+        .cmd(BrowserCommand::GetComputedLabel(elem.element_id()))
+        .await?
+        .value()
+}
+
+/// Upload a file, as a base64 encoded zip archive, to a remote driver,
+/// returning the path of the file on the remote machine.
+pub async fn upload_file(handle: &SessionHandle, zip_base64: String) -> WebDriverResult<String> {
+    handle
+        .cmd(BrowserCommand::UploadFile(zip_base64))
+        .await?
+        .value()
+}
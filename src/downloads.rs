@@ -0,0 +1,118 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// File extensions used by browsers for downloads which are in progress.
+const PARTIAL_EXTENSIONS: &[&str] = &["crdownload", "part", "download", "tmp"];
+
+/// Create a new download directory for a session within `base`.
+pub fn create_session_dir(base: &Path) -> io::Result<PathBuf> {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let dir = base.join(format!("session-{}-{millis}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Remove a session's download directory if nothing was downloaded to it.
+/// Downloaded files are kept for inspection after the session ends.
+pub fn remove_session_dir(dir: &Path) {
+    // Fails, leaving the directory in place, unless it is empty
+    let _ = std::fs::remove_dir(dir);
+}
+
+/// Returns true if `path` is a download which is still in progress.
+fn is_partial(path: &Path) -> bool {
+    let hidden_chrome_file = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with(".com.google.Chrome"));
+    let partial_extension = path.extension().is_some_and(|ext| {
+        PARTIAL_EXTENSIONS
+            .iter()
+            .any(|partial| ext.eq_ignore_ascii_case(partial))
+    });
+    hidden_chrome_file || partial_extension
+}
+
+/// Returns true if `partial` is the in-progress download of `file`, such as
+/// `report.pdf.part` for `report.pdf`.
+fn is_partial_of(partial: &Path, file: &Path) -> bool {
+    is_partial(partial) && partial.with_extension("") == file
+}
+
+/// Find the most recently modified completed download in `files`, given
+/// with their modification times, which isn't in `seen`. Files which are
+/// still being downloaded are skipped, as browsers may create the final file
+/// before it has been completely written. Other partial files, for example
+/// from abandoned downloads, are ignored.
+pub fn find_completed(files: &[(PathBuf, SystemTime)], seen: &[PathBuf]) -> Option<PathBuf> {
+    files
+        .iter()
+        .filter(|(file, _)| !is_partial(file) && !seen.contains(file))
+        .filter(|(file, _)| {
+            !files
+                .iter()
+                .any(|(partial, _)| is_partial_of(partial, file))
+        })
+        .max_by_key(|(_, modified)| *modified)
+        .map(|(file, _)| file.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::find_completed;
+
+    fn at(path: &Path, secs: u64) -> (PathBuf, SystemTime) {
+        (path.to_path_buf(), UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn test_find_completed() {
+        let report = PathBuf::from("/downloads/report.csv");
+        let partial = PathBuf::from("/downloads/invoice.pdf.crdownload");
+        let firefox_partial = PathBuf::from("/downloads/invoice.pdf.part");
+        let invoice = PathBuf::from("/downloads/invoice.pdf");
+        let abandoned = PathBuf::from("/downloads/Unconfirmed 1234.crdownload");
+        let summary = PathBuf::from("/downloads/summary.txt");
+        let seen = [report.clone()];
+
+        assert_eq!(find_completed(&[], &[]), None);
+        assert_eq!(find_completed(&[at(&report, 1)], &[]), Some(report.clone()));
+        assert_eq!(find_completed(&[at(&report, 1)], &seen), None);
+        assert_eq!(
+            find_completed(&[at(&report, 1), at(&partial, 2)], &seen),
+            None
+        );
+        // Firefox creates an empty placeholder alongside the partial file
+        assert_eq!(
+            find_completed(
+                &[at(&report, 1), at(&invoice, 2), at(&firefox_partial, 2)],
+                &seen
+            ),
+            None
+        );
+        assert_eq!(
+            find_completed(&[at(&report, 1), at(&invoice, 2)], &seen),
+            Some(invoice.clone())
+        );
+        // Abandoned downloads don't hold up others
+        assert_eq!(
+            find_completed(&[at(&abandoned, 1), at(&invoice, 2)], &[]),
+            Some(invoice.clone())
+        );
+        // The newest unseen download is chosen
+        assert_eq!(
+            find_completed(&[at(&summary, 5), at(&invoice, 3), at(&report, 9)], &seen),
+            Some(summary)
+        );
+    }
+}
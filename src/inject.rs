@@ -0,0 +1,190 @@
+//! Scripts and stylesheets injected into every new document.
+
+use thirtyfour::WindowHandle;
+
+/// A script registered to run in every new document.
+pub struct Injection {
+    /// The handle given to the flow to remove the injection with.
+    pub handle: String,
+    /// The script to run, guarded so that it only runs once per document.
+    pub source: String,
+    /// The id given by `WebDriver BiDi`, which runs the script in every
+    /// window. Without one, the script is registered with Chrome `DevTools`
+    /// in each window as it is switched to.
+    pub preload_script: Option<String>,
+}
+
+/// A script registered with Chrome `DevTools`, which only runs it in the
+/// window it was registered in.
+#[derive(Clone)]
+pub struct WindowRegistration {
+    /// The window the script is registered in.
+    pub window: WindowHandle,
+    /// The handle of the injection.
+    pub handle: String,
+    /// The identifier given by `DevTools`, which is only unique to the window.
+    pub identifier: String,
+}
+
+/// The scripts registered in a session.
+#[derive(Default)]
+pub struct Injections {
+    next: usize,
+    active: Vec<Injection>,
+    windows: Vec<WindowRegistration>,
+}
+
+impl Injections {
+    /// Prepare to register `script`, returning a new handle for it and its
+    /// source guarded so that it only runs once per document.
+    pub fn prepare(&mut self, script: &str) -> (String, String) {
+        self.next += 1;
+        let handle = format!("inject-{}", self.next);
+        let key = serde_json::Value::from(format!("ta-injected-{handle}"));
+        let source = format!("if (!window[{key}]) {{\nwindow[{key}] = true;\n{script}\n}}\n");
+        (handle, source)
+    }
+
+    /// Record a registered injection.
+    pub fn add(&mut self, injection: Injection) {
+        self.active.push(injection);
+    }
+
+    /// Remove the injection with the handle `handle`, returning it. Any
+    /// registrations of it in windows are left for `removed_from` to find.
+    pub fn remove(&mut self, handle: &str) -> Option<Injection> {
+        let index = self
+            .active
+            .iter()
+            .position(|injection| injection.handle == handle.trim())?;
+        Some(self.active.remove(index))
+    }
+
+    /// The injections which need registering in `window`.
+    pub fn missing_from(&self, window: &WindowHandle) -> Vec<&Injection> {
+        self.active
+            .iter()
+            .filter(|injection| injection.preload_script.is_none())
+            .filter(|injection| {
+                !self.windows.iter().any(|registration| {
+                    registration.window == *window && registration.handle == injection.handle
+                })
+            })
+            .collect()
+    }
+
+    /// The registrations in `window` of injections which have been removed.
+    pub fn removed_from(&self, window: &WindowHandle) -> Vec<WindowRegistration> {
+        self.windows
+            .iter()
+            .filter(|registration| registration.window == *window)
+            .filter(|registration| {
+                !self
+                    .active
+                    .iter()
+                    .any(|injection| injection.handle == registration.handle)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Record that an injection has been registered in a window.
+    pub fn register(&mut self, registration: WindowRegistration) {
+        self.windows.push(registration);
+    }
+
+    /// Record that an injection is no longer registered in a window.
+    pub fn unregister(&mut self, window: &WindowHandle, handle: &str) {
+        self.windows
+            .retain(|registration| registration.window != *window || registration.handle != handle);
+    }
+
+    /// Forget the registrations in a window which has closed.
+    pub fn forget_window(&mut self, window: &WindowHandle) {
+        self.windows
+            .retain(|registration| registration.window != *window);
+    }
+
+    /// Forget every injection, for example once the session has ended.
+    pub fn clear(&mut self) {
+        self.active.clear();
+        self.windows.clear();
+    }
+}
+
+/// Build a script which adds `css` to the document as a stylesheet.
+pub fn css_script(css: &str) -> String {
+    include_str!("scripts/inject_css.js")
+        .replace("/* CSS */", &serde_json::Value::from(css).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use thirtyfour::WindowHandle;
+
+    use super::{css_script, Injection, Injections, WindowRegistration};
+
+    #[test]
+    fn test_injections() {
+        let mut injections = Injections::default();
+        let (a, source) = injections.prepare("stub();");
+        assert!(source.contains(r#"window["ta-injected-inject-1"]"#));
+        assert!(source.contains("stub();"));
+        injections.add(Injection {
+            handle: a.clone(),
+            source,
+            preload_script: None,
+        });
+        let (b, _) = injections.prepare("other();");
+        assert_ne!(a, b);
+
+        assert!(injections.remove(&b).is_none());
+        assert_eq!(
+            injections.remove(&a).map(|injection| injection.handle),
+            Some(a.clone())
+        );
+        assert!(injections.remove(&a).is_none());
+    }
+
+    #[test]
+    fn test_window_registrations() {
+        let first = WindowHandle::from("first".to_string());
+        let second = WindowHandle::from("second".to_string());
+        let mut injections = Injections::default();
+        for preload_script in [None, Some("preload-1".to_string())] {
+            let (handle, source) = injections.prepare("stub();");
+            injections.add(Injection {
+                handle,
+                source,
+                preload_script,
+            });
+        }
+
+        // Preload scripts already run in every window
+        let missing = injections.missing_from(&first);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].handle, "inject-1");
+        injections.register(WindowRegistration {
+            window: first.clone(),
+            handle: "inject-1".to_string(),
+            identifier: "1".to_string(),
+        });
+        assert!(injections.missing_from(&first).is_empty());
+        assert_eq!(injections.missing_from(&second).len(), 1);
+
+        injections.remove("inject-1");
+        assert!(injections.missing_from(&second).is_empty());
+        assert!(injections.removed_from(&second).is_empty());
+        let removed = injections.removed_from(&first);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].identifier, "1");
+        injections.unregister(&first, "inject-1");
+        assert!(injections.removed_from(&first).is_empty());
+    }
+
+    #[test]
+    fn test_css_script() {
+        let script = css_script("a::after { content: \"}\"; }");
+        assert!(script.contains(r#"var css = "a::after { content: \"}\"; }";"#));
+    }
+}
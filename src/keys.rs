@@ -0,0 +1,278 @@
+//! Parsing human-readable key names, such as `Enter` or `Ctrl+Shift+K`, into
+//! the characters `WebDriver` uses to represent keys.
+
+use thirtyfour::Key;
+
+/// Find the key with the name `name`, ignoring case. Any single character is
+/// treated as that character's key.
+pub fn parse_key(name: &str) -> Result<char, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(c);
+    }
+
+    let key = match name.to_ascii_lowercase().as_str() {
+        "null" => Key::Null,
+        "cancel" => Key::Cancel,
+        "help" => Key::Help,
+        "backspace" | "bksp" => Key::Backspace,
+        "tab" => Key::Tab,
+        "clear" => Key::Clear,
+        "return" => Key::Return,
+        "enter" => Key::Enter,
+        "shift" => Key::Shift,
+        "ctrl" | "control" => Key::Control,
+        "alt" | "option" => Key::Alt,
+        "pause" => Key::Pause,
+        "esc" | "escape" => Key::Escape,
+        "space" => Key::Space,
+        "pageup" | "pgup" => Key::PageUp,
+        "pagedown" | "pgdn" => Key::PageDown,
+        "end" => Key::End,
+        "home" => Key::Home,
+        "left" | "arrowleft" => Key::Left,
+        "up" | "arrowup" => Key::Up,
+        "right" | "arrowright" => Key::Right,
+        "down" | "arrowdown" => Key::Down,
+        "ins" | "insert" => Key::Insert,
+        "del" | "delete" => Key::Delete,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        "meta" | "cmd" | "command" | "win" | "windows" | "super" => Key::Meta,
+        "plus" => return Ok('+'),
+        _ => return Err(format!("{name:?} isn't a recognised key.")),
+    };
+    Ok(key.into())
+}
+
+/// Returns true if `key` is a modifier, which stays held while typing until
+/// the null key is sent.
+fn is_modifier(key: char) -> bool {
+    [Key::Shift, Key::Control, Key::Alt, Key::Meta]
+        .into_iter()
+        .any(|modifier| char::from(modifier) == key)
+}
+
+/// Parse a chord of keys joined by `+`, for example `Ctrl+Shift+K`, into the
+/// keys in the order they should be pressed. A `+` key can be written as
+/// `Plus`, or as the last key of the chord, such as `Ctrl++`. Letters pressed
+/// with modifiers are upper case only if Shift is part of the chord, so
+/// `Ctrl+A` selects all rather than pressing Ctrl+Shift+A.
+pub fn parse_chord(chord: &str) -> Result<Vec<char>, String> {
+    let chord = chord.trim();
+    if chord == "+" {
+        return Ok(vec!['+']);
+    }
+    let (names, trailing_plus) = match chord.strip_suffix("++") {
+        Some(rest) => (rest, true),
+        None => (chord, false),
+    };
+
+    let mut keys = names
+        .split('+')
+        .map(|name| match name.trim() {
+            "" => Err(format!("{chord:?} isn't a valid key chord.")),
+            name => parse_key(name),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if trailing_plus {
+        keys.push('+');
+    }
+    if keys.iter().any(|key| is_modifier(*key)) {
+        let shift = keys.contains(&Key::Shift.into());
+        for key in &mut keys {
+            *key = if shift {
+                key.to_ascii_uppercase()
+            } else {
+                key.to_ascii_lowercase()
+            };
+        }
+    }
+    Ok(keys)
+}
+
+/// Translate text containing special keys in braces, for example
+/// `Hello{Enter}` or `{Ctrl+A}{Delete}`, into the text to send to `WebDriver`.
+/// Modifiers in a chord are released at the end of it. A literal brace is
+/// written by doubling it, as `{{` or `}}`.
+pub fn parse_key_syntax(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let mut chord = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => chord.push(c),
+                        None => {
+                            return Err(format!(
+                            "The key {{{chord}}} isn't closed. Use {{{{ to type a literal brace."
+                        ))
+                        }
+                    }
+                }
+                let keys = parse_chord(&chord)?;
+                let release = keys.iter().any(|key| is_modifier(*key));
+                result.extend(keys);
+                if release {
+                    result.push(Key::Null.into());
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// Returns true if `key` is one of `WebDriver`'s special keys rather than a
+/// character to type.
+pub fn is_special(key: char) -> bool {
+    ('\u{E000}'..='\u{F8FF}').contains(&key)
+}
+
+/// Split keys to send into individual keystrokes, keeping each chord of
+/// modifiers together with the key pressed with it and the null key which
+/// releases them.
+pub fn split_keystrokes(keys: &str) -> Vec<String> {
+    let mut keystrokes = vec![];
+    let mut chord = String::new();
+    for key in keys.chars() {
+        if is_modifier(key) || !chord.is_empty() {
+            chord.push(key);
+            if key == char::from(Key::Null) {
+                keystrokes.push(std::mem::take(&mut chord));
+            }
+        } else {
+            keystrokes.push(key.to_string());
+        }
+    }
+    if !chord.is_empty() {
+        keystrokes.push(chord);
+    }
+    keystrokes
+}
+
+/// The text which a keystroke from `split_keystrokes` types into a field.
+/// Special keys type nothing. Letters pressed with Shift are typed in upper
+/// case, but chords with other modifiers, such as `Ctrl+A`, type nothing.
+pub fn typed_text(keystroke: &str) -> String {
+    let shift = char::from(Key::Shift);
+    if keystroke
+        .chars()
+        .any(|key| is_modifier(key) && key != shift)
+    {
+        return String::new();
+    }
+    let shifted = keystroke.contains(shift);
+    keystroke
+        .chars()
+        .filter(|key| !is_special(*key))
+        .map(|key| {
+            if shifted {
+                key.to_ascii_uppercase()
+            } else {
+                key
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use thirtyfour::Key;
+
+    use super::{parse_chord, parse_key, parse_key_syntax, split_keystrokes, typed_text};
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("a"), Ok('a'));
+        assert_eq!(parse_key("Enter"), Ok(Key::Enter.into()));
+        assert_eq!(parse_key("ESC"), Ok(Key::Escape.into()));
+        assert_eq!(parse_key("f12"), Ok(Key::F12.into()));
+        assert!(parse_key("Hyper").is_err());
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        assert_eq!(
+            parse_chord("Ctrl+Shift+K"),
+            Ok(vec![Key::Control.into(), Key::Shift.into(), 'K'])
+        );
+        assert_eq!(parse_chord("Ctrl+A"), Ok(vec![Key::Control.into(), 'a']));
+        assert_eq!(parse_chord(" Tab "), Ok(vec![Key::Tab.into()]));
+        assert_eq!(parse_chord("Ctrl++"), Ok(vec![Key::Control.into(), '+']));
+        assert_eq!(parse_chord("+"), Ok(vec!['+']));
+        assert!(parse_chord("Ctrl+").is_err());
+        assert!(parse_chord("").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_syntax() {
+        let ctrl = char::from(Key::Control);
+        let null = char::from(Key::Null);
+        let enter = char::from(Key::Enter);
+
+        assert_eq!(parse_key_syntax("plain text"), Ok("plain text".to_string()));
+        assert_eq!(
+            parse_key_syntax("Hello{Enter}"),
+            Ok(format!("Hello{enter}"))
+        );
+        assert_eq!(parse_key_syntax("{Ctrl+A}x"), Ok(format!("{ctrl}a{null}x")));
+        assert_eq!(parse_key_syntax("{{Enter}}"), Ok("{Enter}".to_string()));
+        assert_eq!(parse_key_syntax("a}b"), Ok("a}b".to_string()));
+        assert!(parse_key_syntax("{Enter").is_err());
+        assert!(parse_key_syntax("{Nope}").is_err());
+    }
+
+    #[test]
+    fn test_split_keystrokes() {
+        let keys = parse_key_syntax("ab{Ctrl+A}{Enter}").unwrap();
+        let ctrl = char::from(Key::Control);
+        let null = char::from(Key::Null);
+        let enter = char::from(Key::Enter);
+        assert_eq!(
+            split_keystrokes(&keys),
+            vec![
+                "a".to_string(),
+                "b".to_string(),
+                format!("{ctrl}a{null}"),
+                enter.to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_typed_text() {
+        let typed = |text| {
+            split_keystrokes(&parse_key_syntax(text).unwrap())
+                .iter()
+                .map(String::as_str)
+                .map(typed_text)
+                .collect::<String>()
+        };
+        assert_eq!(typed("ab{Enter}"), "ab");
+        assert_eq!(typed("{Shift+a}b"), "Ab");
+        assert_eq!(typed("x{Ctrl+A}{Delete}y"), "xy");
+        assert_eq!(typed("{Shift}c"), "c");
+    }
+}
@@ -1,19 +1,85 @@
 #![warn(clippy::pedantic)]
 
-use std::{process::Child, time::Duration};
+use std::{path::PathBuf, process::Child, sync::Arc, time::Duration};
 
 use testangel_engine::{engine, Evidence, EvidenceContent};
-use thirtyfour::prelude::*;
+use thirtyfour::{
+    common::capabilities::firefox::FirefoxPreferences, components::SelectElement, prelude::*,
+    session::handle::SessionHandle, CapabilitiesHelper, WindowHandle,
+};
 use thiserror::Error;
 use tokio::runtime::{self, Runtime};
 
 const DEFAULT_URI: &str = "data:text/html;base64,PGh0bWw+PGhlYWQ+PHRpdGxlPkJyb3dzZXIgQXV0b21hdGlvbjwvdGl0bGU+PC9oZWFkPjxib2R5IHN0eWxlPSJvdmVyZmxvdzpoaWRkZW47Ij48aDEgc3R5bGU9ImRpc3BsYXk6ZmxleDtqdXN0aWZ5LWNvbnRlbnQ6Y2VudGVyO2FsaWduLWl0ZW1zOmNlbnRlcjtoZWlnaHQ6MTAwJTsiPlRlc3RBbmdlbCBCcm93c2VyIEF1dG9tYXRpb24gc3RhcnRpbmcuLi48L2gxPjwvYm9keT48L2h0bWw+";
+mod bidi;
+mod commands;
+mod downloads;
+mod inject;
+mod keys;
+mod registry;
+mod relative;
+mod table;
+mod upload;
 mod utils;
 
+use inject::{Injection, Injections, WindowRegistration};
+use registry::{ElementRegistry, Locator, Strategy};
+use relative::Relation;
+
+/// MIME types which Firefox should download without asking.
+const FIREFOX_SAVE_TO_DISK_TYPES: &str = "application/octet-stream,application/pdf,application/zip,application/json,application/xml,text/csv,text/plain,text/xml,application/vnd.ms-excel,application/vnd.openxmlformats-officedocument.spreadsheetml.sheet,application/msword,application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+/// The browser being automated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BrowserKind {
+    Chrome,
+    Firefox,
+}
+
 #[derive(Error, Debug)]
 pub enum EngineError {
     #[error("The browser robot hasn't been initialised before use.")]
     NotInitialised,
+    #[error("No frame could be found matching {0}.")]
+    FrameNotFound(String),
+    #[error("Invalid element: {0}")]
+    InvalidElement(String),
+    #[error("No element with the handle `{0}` exists. Element handles are produced by the 'Select Element' instructions.")]
+    UnknownElement(String),
+    #[error(
+        "The element `{0}` was selected in a previous browser session and can no longer be used."
+    )]
+    ElementFromOtherSession(String),
+    #[error("The element `{0}` is stale: it has been removed from the page, or the page has changed since it was selected.")]
+    StaleElement(String),
+    #[error("This element belongs to {element}, but the browser is currently in {current}. Switch frames before using it.")]
+    WrongFrame { element: String, current: String },
+    #[error("No window could be found matching {0}.")]
+    WindowNotFound(String),
+    #[error("No new window opened before the timeout.")]
+    NoNewWindow,
+    #[error("The viewport could not be resized to {requested:?}, it is {actual:?}.")]
+    ViewportSize {
+        requested: (i64, i64),
+        actual: (i64, i64),
+    },
+    #[error("No element could be found {0}.")]
+    ElementNotFound(String),
+    #[error("The script arguments aren't a valid JSON array: {0}")]
+    InvalidScriptArguments(String),
+    #[error("The script failed: {0}")]
+    ScriptFailed(String),
+    #[error("No column has the header {header:?}. The column headers are {headers:?}.")]
+    ColumnNotFound {
+        header: String,
+        headers: Vec<String>,
+    },
+    #[error("WebDriver BiDi failed: {0}")]
+    BiDi(String),
+    #[error("No injected script or stylesheet has the handle `{0}`.")]
+    UnknownInjection(String),
+    #[error(transparent)]
+    WebDriver(#[from] WebDriverError),
 }
 
 engine! {
@@ -28,6 +94,16 @@ engine! {
         child_driver: Option<Child>,
         timeout: Duration,
         interval: Duration,
+        frame: Vec<String>,
+        windows_seen: Vec<WindowHandle>,
+        elements: ElementRegistry,
+        browser: Option<BrowserKind>,
+        download_dir: Option<PathBuf>,
+        downloads_seen: Vec<PathBuf>,
+        parse_key_syntax: bool,
+        injections: Injections,
+        animations_injection: Option<String>,
+        bidi: Option<bidi::BiDi>,
     }
 
     impl Browser {
@@ -41,17 +117,33 @@ engine! {
         )]
         fn connect() {
             use std::{env, process};
+            use thirtyfour::extensions::cdp::ChromeDevTools;
 
             state.rt = Some(runtime::Builder::new_current_thread().enable_all().build()?);
             let use_chrome = env::var("TA_BROWSER_USE_CHROME").ok();
             let use_firefox = env::var("TA_BROWSER_USE_FIREFOX").ok();
             let webdriver_port = env::var("TA_BROWSER_WEBDRIVER_PORT").ok();
+            let download_base = env::var("TA_BROWSER_DOWNLOAD_DIR").map_or_else(|_| env::temp_dir().join("testangel-browser-downloads"), PathBuf::from);
+            let create_download_dir = || {
+                downloads::create_session_dir(&download_base)
+                    .map_err(|e| format!("Failed to create download directory: {e}"))
+            };
 
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = if let Some(chromedriver_path) = use_chrome {
+            let (driver, download_dir) = if let Some(chromedriver_path) = use_chrome {
+                state.browser = Some(BrowserKind::Chrome);
+                let download_dir = create_download_dir()?;
+                let download_dir_str = download_dir.to_string_lossy().to_string();
+                let mut caps = DesiredCapabilities::chrome();
+                caps.add_experimental_option("prefs", serde_json::json!({
+                    "download.default_directory": download_dir_str,
+                    "download.prompt_for_download": false,
+                    "plugins.always_open_pdf_externally": true,
+                }))?;
+
                 // Try to connect to running chromedriver
                 let port = webdriver_port.unwrap_or("9515".to_string());
-                if let Ok(driver) = rt.block_on(WebDriver::new(&format!("http://localhost:{port}"), DesiredCapabilities::chrome())) {
+                let driver = if let Ok(driver) = rt.block_on(WebDriver::new(&format!("http://localhost:{port}"), caps.clone())) {
                     driver
                 } else {
                     // Use chromedriver at path
@@ -62,16 +154,39 @@ engine! {
                         .spawn()
                         .map_err(|e| format!("Failed to start chromedriver: {e}"))?);
                     std::thread::sleep(Duration::from_millis(500));
-                    let mut caps = DesiredCapabilities::chrome();
                     for arg in browser_args {
                         let _ = caps.add_arg(&arg);
                     }
                     rt.block_on(WebDriver::new(&format!("http://localhost:{port}"), caps))?
-                }
+                };
+
+                // Preferences aren't applied to an already running browser, and
+                // are ignored by some headless modes, so also set this over CDP.
+                let dev_tools = ChromeDevTools::new(driver.handle.clone());
+                let _ = rt.block_on(dev_tools.execute_cdp_with_params("Browser.setDownloadBehavior", serde_json::json!({
+                    "behavior": "allow",
+                    "downloadPath": download_dir_str,
+                })));
+                (driver, download_dir)
             } else if let Some(geckodriver_path) = use_firefox {
+                state.browser = Some(BrowserKind::Firefox);
+                let download_dir = create_download_dir()?;
+                let download_dir_str = download_dir.to_string_lossy().to_string();
+                let mut caps = DesiredCapabilities::firefox();
+                let mut prefs = FirefoxPreferences::new();
+                prefs.set("browser.download.dir", &download_dir_str)?;
+                prefs.set("browser.download.folderList", 2)?;
+                prefs.set("browser.download.useDownloadDir", true)?;
+                prefs.set("browser.download.manager.showWhenStarting", false)?;
+                prefs.set("browser.helperApps.neverAsk.saveToDisk", FIREFOX_SAVE_TO_DISK_TYPES)?;
+                prefs.set("pdfjs.disabled", true)?;
+                caps.set_preferences(prefs)?;
+                // Enables WebDriver BiDi, used for page injection
+                caps.set_base_capability("webSocketUrl", true)?;
+
                 // Try to connect to running geckodriver
                 let port = webdriver_port.unwrap_or("4444".to_string());
-                if let Ok(driver) = rt.block_on(WebDriver::new(&format!("http://localhost:{port}"), DesiredCapabilities::firefox())) {
+                let driver = if let Ok(driver) = rt.block_on(WebDriver::new(&format!("http://localhost:{port}"), caps.clone())) {
                     driver
                 } else {
                     // Use geckodriver at path
@@ -83,21 +198,30 @@ engine! {
                         .map_err(|e| format!("Failed to start geckodriver: {e}"))?);
                     // Give it time to start
                     std::thread::sleep(Duration::from_millis(500));
-                    let mut caps = DesiredCapabilities::firefox();
                     for arg in browser_args {
                         let _ = caps.add_arg(&arg);
                     }
                     rt.block_on(WebDriver::new(&format!("http://localhost:{port}"), caps))?
-                }
+                };
+                (driver, download_dir)
             } else {
                 // TODO Download a browser and driver
                 Err("This functionality is currently not implemented in the engine. Please set either `TA_BROWSER_USE_CHROME` or `TA_BROWSER_USE_FIREFOX` and try again.")?;
                 unreachable!()
             };
 
+            state.download_dir = Some(download_dir);
+            state.downloads_seen.clear();
+
             // Has to use this strange format to prevent data URLs being mangled.
             rt.block_on(driver.goto(DEFAULT_URI))?;
+            state.windows_seen = rt.block_on(driver.windows())?;
             state.driver = Some(driver);
+            state.frame.clear();
+            state.injections.clear();
+            state.animations_injection = None;
+            state.bidi = None;
+            state.parse_key_syntax = true;
         }
 
         /// Quit the browser robot session.
@@ -110,6 +234,14 @@ engine! {
         fn quit() {
             let rt = state.rt.take().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.take().ok_or(EngineError::NotInitialised)?;
+            state.frame.clear();
+            state.windows_seen.clear();
+            state.injections.clear();
+            state.animations_injection = None;
+            state.bidi = None;
+            if let Some(dir) = state.download_dir.take() {
+                downloads::remove_session_dir(&dir);
+            }
             rt.block_on(driver.quit())?;
         }
 
@@ -155,7 +287,7 @@ engine! {
             rt.block_on(driver.get_alert_text())?
         }
 
-        /// Send keys to an alert box.
+        /// Send keys to an alert box. Special keys can be written in braces, for example `{Enter}`, so literal braces must be doubled, as `{{` or `}}`.
         #[instruction(
             id = "browser-alert-send-text",
             lua_name = "AlertType",
@@ -165,6 +297,7 @@ engine! {
         fn alert_send_text(
             keys: String,
         ) {
+            let keys = state.keys(&keys)?;
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
             rt.block_on(driver.send_alert_text(keys))?;
@@ -200,6 +333,54 @@ engine! {
             ret.json().to_string()
         }
 
+        /// Execute JavaScript with arguments, given as a JSON array and available to the script as `arguments[0]` and so on. Element handles in the arguments are passed to the script as elements. Elements in the return value are replaced by new element handles, and the first is also output on its own.
+        #[instruction(
+            id = "browser-execute-javascript-with-arguments",
+            lua_name = "ExecuteJavaScriptWithArguments",
+            name = "Execute JavaScript with Arguments",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn execute_javascript_with_args(
+            #[arg(name = "JavaScript")] script: String,
+            #[arg(name = "Arguments as JSON Array")] args: String,
+        ) -> (
+            #[output(id = "return", name = "Return Value as JSON String")] String,
+            #[output(id = "element", name = "First Returned Element")] String,
+        ) {
+            let args = state.script_args(&args)?;
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let ret = rt.block_on(driver.execute(&script, args))?.json().clone();
+            let (ret, first_elem) = state.script_return(ret)?;
+            (ret.to_string(), first_elem.unwrap_or_default())
+        }
+
+        /// Execute asynchronous JavaScript. The script is given its arguments followed by a callback, so the last argument should be called with the result. Alternatively the script may return a Promise, which is waited for. Arguments and returned elements are handled as for 'Execute JavaScript with Arguments'. The script must finish within the session's script timeout.
+        #[instruction(
+            id = "browser-execute-async-javascript",
+            lua_name = "ExecuteAsyncJavaScript",
+            name = "Execute Async JavaScript",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn execute_async_javascript(
+            #[arg(name = "JavaScript")] script: String,
+            #[arg(name = "Arguments as JSON Array")] args: String,
+        ) -> (
+            #[output(id = "return", name = "Return Value as JSON String")] String,
+            #[output(id = "element", name = "First Returned Element")] String,
+        ) {
+            let args = state.script_args(&args)?;
+            let script = include_str!("scripts/execute_async.js").replace("/* SCRIPT */", &script);
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let ret = rt.block_on(driver.execute_async(&script, args))?.json().clone();
+            if let Some(error) = ret.as_object().filter(|ret| ret.len() == 1).and_then(|ret| ret.get("ta-error")) {
+                Err(EngineError::ScriptFailed(error.as_str().unwrap_or_default().to_string()))?;
+            }
+            let (ret, first_elem) = state.script_return(ret)?;
+            (ret.to_string(), first_elem.unwrap_or_default())
+        }
+
         /// Direct the browser to a URL.
         #[instruction(
             id = "browser-goto",
@@ -213,407 +394,1403 @@ engine! {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
             rt.block_on(driver.goto(url))?;
+            // Navigation always returns to the top-level document
+            state.frame.clear();
         }
 
-        /* CHROME DEVTOOLS PROTOCOL */
-
-        /// Execute arbitrary JavaScript.
+        /// Set how long scripts run by 'Execute Async JavaScript' may take before they are abandoned.
         #[instruction(
-            id = "browser-cdp-execute",
-            lua_name = "CDPExecute",
-            name = "Chrome DevTools: Execute",
+            id = "browser-set-script-timeout",
+            lua_name = "SetScriptTimeout",
+            name = "Set Script Timeout",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn cdp_execute(
-            #[arg(name = "Command")] cmd: String,
-        ) -> #[output(id = "return", name = "Return Value as JSON String")] String {
-            use thirtyfour::extensions::cdp::ChromeDevTools;
+        fn set_script_timeout(
+            #[arg(name = "Timeout (seconds)")] timeout: i32,
+        ) {
+            let timeout = u64::try_from(timeout).map_err(|_| "The timeout can't be negative.")?;
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let dev_tools = ChromeDevTools::new(driver.handle.clone());
-            let ret = rt.block_on(dev_tools.execute_cdp(&cmd))?;
-            serde_json::to_string(&ret).map_err(|_| "Return value couldn't be converted to JSON string")?
+            rt.block_on(driver.set_script_timeout(Duration::from_secs(timeout)))?;
         }
 
-        /// Direct the browser to a URL.
+        /// Turn the special key syntax used by 'Send Keys' instructions, such as `{Enter}`, on or off for this session. When off, text is typed exactly as given. It is turned back on when connecting to a new browser session.
         #[instruction(
-            id = "browser-cdp-execute-with-params",
-            lua_name = "CDPExecuteWithParams",
-            name = "Chrome DevTools: Execute with Parameters",
+            id = "browser-set-key-syntax",
+            lua_name = "SetKeySyntaxEnabled",
+            name = "Set Special Key Syntax Enabled",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn cdp_execute_with_params(
-            #[arg(name = "Command")] cmd: String,
-            #[arg(name = "Parameter as JSON String")] params: String
-        ) -> #[output(id = "return", name = "Return Value as JSON String")] String {
-            use thirtyfour::extensions::cdp::ChromeDevTools;
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let dev_tools = ChromeDevTools::new(driver.handle.clone());
-            let ret = rt.block_on(dev_tools.execute_cdp_with_params(&cmd, serde_json::from_str(&params).map_err(|_| "Parameters for CDP are not a valid JSON string")?))?;
-            serde_json::to_string(&ret).map_err(|_| "Return value couldn't be converted to JSON string")?
+        fn set_key_syntax(
+            enabled: bool,
+        ) {
+            state.parse_key_syntax = enabled;
         }
 
-        /* ELEMENT SELECTION */
+        /* FRAMES */
 
-        /// Select Element By: Class Name
+        /// Switch into the frame (or iframe) given by an element.
         #[instruction(
-            id = "browser-select-by-class-name",
-            lua_name = "SelectByClassName",
-            name = "Select Element By: Class Name",
+            id = "browser-frame-switch-to-element",
+            lua_name = "SwitchToFrameByElement",
+            name = "Frame: Switch to Frame by Element",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_class_name(
-            #[arg(name = "Class Name")] class: String,
-        ) -> #[output(id = "element", name = "Element")] String {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::ClassName(class))
-                .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+        fn frame_switch_to_element(
+            element: String,
+        ) {
+            let elem = state.element(&element)?;
+            state.enter_frame(elem)?;
         }
 
-        /// Select Element By: CSS Selector
+        /// Switch into a frame (or iframe) by its index within the current document, starting at 0.
         #[instruction(
-            id = "browser-select-by-css",
-            lua_name = "SelectByCSS",
-            name = "Select Element By: CSS Selector",
+            id = "browser-frame-switch-to-index",
+            lua_name = "SwitchToFrameByIndex",
+            name = "Frame: Switch to Frame by Index",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_css(
-            #[arg(name = "CSS Selector")] css: String,
-        ) -> #[output(id = "element", name = "Element")] String {
+        fn frame_switch_to_index(
+            index: i32,
+        ) {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::Css(css))
-                .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+            let frames = rt.block_on(driver.find_all(By::Css("iframe, frame")))?;
+            let elem = usize::try_from(index)
+                .ok()
+                .and_then(|i| frames.into_iter().nth(i))
+                .ok_or_else(|| EngineError::FrameNotFound(format!("index {index}")))?;
+            state.enter_frame(elem)?;
         }
 
-        /// Select Element By: ID
+        /// Switch into a frame (or iframe) by its name or ID attribute.
         #[instruction(
-            id = "browser-select-by-id",
-            lua_name = "SelectByID",
-            name = "Select Element By: ID",
+            id = "browser-frame-switch-to-name",
+            lua_name = "SwitchToFrameByName",
+            name = "Frame: Switch to Frame by Name",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_id(
-            #[arg(name = "ID")] id: String,
-        ) -> #[output(id = "element", name = "Element")] String {
+        fn frame_switch_to_name(
+            name: String,
+        ) {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::Id(id))
+            let quoted = utils::css_string(&name);
+            let elem = rt.block_on(driver.query(By::Css(format!(
+                    "iframe[name={quoted}], frame[name={quoted}], iframe[id={quoted}], frame[id={quoted}]"
+                )))
                 .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+                .first())
+                .map_err(|_| EngineError::FrameNotFound(format!("name {name:?}")))?;
+            state.enter_frame(elem)?;
         }
 
-        /// Select Element By: Link Text
+        /// Switch to the parent of the current frame.
         #[instruction(
-            id = "browser-select-by-link-text",
-            lua_name = "SelectByLinkText",
-            name = "Select Element By: Link Text",
+            id = "browser-frame-switch-to-parent",
+            lua_name = "SwitchToParentFrame",
+            name = "Frame: Switch to Parent Frame",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_link_text(
-            #[arg(id = "link-text", name = "Link Text")] link_text: String,
-        ) -> #[output(id = "element", name = "Element")] String {
+        fn frame_switch_to_parent() {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::LinkText(link_text))
-                .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+            rt.block_on(driver.enter_parent_frame())?;
+            state.frame.pop();
         }
 
-        /// Select Element By: HTML 'name' attribute
+        /// Switch back to the top-level document, leaving all frames.
         #[instruction(
-            id = "browser-select-by-name",
-            lua_name = "SelectByName",
-            name = "Select Element By: Name",
+            id = "browser-frame-switch-to-default",
+            lua_name = "SwitchToDefaultContent",
+            name = "Frame: Switch to Default Content",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_name(
-            #[arg(name = "Name")] name: String,
-        ) -> #[output(id = "element", name = "Element")] String {
+        fn frame_switch_to_default() {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::Name(name))
-                .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+            rt.block_on(driver.enter_default_frame())?;
+            state.frame.clear();
         }
 
-        /// Select Element By: Tag
+        /* WINDOWS */
+
+        /// List the handles of all open windows and tabs.
         #[instruction(
-            id = "browser-select-by-tag",
-            lua_name = "SelectByTag",
-            name = "Select Element By: Tag",
+            id = "browser-window-list",
+            lua_name = "ListWindows",
+            name = "Window: List Handles",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_tag(
-            tag: String,
-        ) -> #[output(id = "element", name = "Element")] String {
+        fn window_list() -> #[output(id = "handles", name = "Window Handles as JSON Array")] String {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::Tag(tag))
-                .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+            let handles = rt.block_on(driver.windows())?;
+            serde_json::Value::from(handles.iter().map(ToString::to_string).collect::<Vec<_>>()).to_string()
         }
 
-        /// Select Element By: XPath
+        /// Get the handle of the current window or tab.
         #[instruction(
-            id = "browser-select-by-xpath",
-            lua_name = "SelectByXPath",
-            name = "Select Element By: XPath",
+            id = "browser-window-current",
+            lua_name = "GetCurrentWindow",
+            name = "Window: Get Current Handle",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn select_by_xpath(
-            #[arg(name = "XPath")] xpath: String,
-        ) -> #[output(id = "element", name = "Element")] String {
+        fn window_current() -> #[output(id = "handle", name = "Window Handle")] String {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = rt.block_on(driver.query(By::XPath(xpath))
-                .wait(state.timeout, state.interval)
-                .first())?;
-            utils::serialise_elem(&elem)?
+            rt.block_on(driver.window())?.to_string()
         }
 
-        /* ELEMENT ACTIONS */
-        /// Get attribute
+        /// Open a new tab and switch to it.
         #[instruction(
-            id = "browser-element-attr",
-            lua_name = "GetElementAttribute",
-            name = "Element: Get Attribute",
+            id = "browser-window-new-tab",
+            lua_name = "OpenNewTab",
+            name = "Window: Open New Tab",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_attr(
-            element: String,
-            #[arg(name = "Attribute Name")] name: String,
-        ) -> #[output(id = "attr", name = "Attribute Value")] String {
+        fn window_new_tab() -> #[output(id = "handle", name = "Window Handle")] String {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            let val = rt.block_on(elem.attr(&name))?;
-            val.unwrap_or(String::new())
+            let handle = rt.block_on(driver.new_tab())?;
+            state.switch_window(handle.clone())?;
+            handle.to_string()
         }
 
-        /// Get class name
+        /// Open a new window and switch to it.
         #[instruction(
-            id = "browser-element-class-name",
-            lua_name = "GetElementClassName",
-            name = "Element: Get Class Name",
+            id = "browser-window-new-window",
+            lua_name = "OpenNewWindow",
+            name = "Window: Open New Window",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_class_name(
-            element: String,
-        ) -> #[output(id = "class", name = "Class Name")] String {
+        fn window_new_window() -> #[output(id = "handle", name = "Window Handle")] String {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            let val = rt.block_on(elem.class_name())?;
-            val.unwrap_or(String::new())
+            let handle = rt.block_on(driver.new_window())?;
+            state.switch_window(handle.clone())?;
+            handle.to_string()
         }
 
-        /// Clear the contents, for example of a text field.
+        /// Switch to the window or tab with the given handle.
         #[instruction(
-            id = "browser-element-clear",
-            lua_name = "ClearElement",
-            name = "Element: Clear",
+            id = "browser-window-switch-to-handle",
+            lua_name = "SwitchToWindowByHandle",
+            name = "Window: Switch by Handle",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_clear(
-            element: String,
+        fn window_switch_to_handle(
+            #[arg(name = "Window Handle")] handle: String,
         ) {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.clear())?;
+            state.switch_window(WindowHandle::from(handle))?;
         }
 
-        /// Click element
+        /// Switch to the first window or tab with a title matching a pattern. `*` matches any text and `?` matches any single character.
         #[instruction(
-            id = "browser-element-click",
-            lua_name = "ClickElement",
-            name = "Element: Click",
+            id = "browser-window-switch-to-title",
+            lua_name = "SwitchToWindowByTitle",
+            name = "Window: Switch by Title",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_click(
-            element: String
+        fn window_switch_to_title(
+            #[arg(name = "Title Pattern")] pattern: String,
         ) {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.click())?;
+            let description = format!("title {pattern:?}");
+            let pattern = pattern.as_str();
+            state.switch_window_matching(&description, |session| async move {
+                let title = session.title().await?;
+                Ok(utils::wildcard_match(pattern, title))
+            })?;
         }
 
-        /// Get CSS value
+        /// Switch to the first window or tab with a URL matching a pattern. `*` matches any text and `?` matches any single character.
         #[instruction(
-            id = "browser-element-css-value",
-            lua_name = "GetElementCSSValue",
-            name = "Element: Get CSS Value",
+            id = "browser-window-switch-to-url",
+            lua_name = "SwitchToWindowByURL",
+            name = "Window: Switch by URL",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_css_value(
-            element: String,
-            #[arg(name = "CSS Property")] name: String,
-        ) -> #[output(id = "value", name = "value")] String {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.css_value(&name))?
+        fn window_switch_to_url(
+            #[arg(name = "URL Pattern")] pattern: String,
+        ) {
+            let description = format!("URL {pattern:?}");
+            let pattern = pattern.as_str();
+            state.switch_window_matching(&description, |session| async move {
+                let url = session.current_url().await?;
+                Ok(utils::wildcard_match(pattern, url.as_str()))
+            })?;
         }
 
-        /// Focus this element using JavaScript
+        /// Close the current window or tab, then switch to another open window if there is one.
         #[instruction(
-            id = "browser-element-focus",
-            lua_name = "FocusElement",
-            name = "Element: Focus",
+            id = "browser-window-close",
+            lua_name = "CloseWindow",
+            name = "Window: Close Current",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_focus(
-            element: String,
-        ) {
+        fn window_close() {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.focus())?;
+            let closed = rt.block_on(driver.window())?;
+            rt.block_on(driver.close_window())?;
+            state.windows_seen.retain(|h| *h != closed);
+            state.injections.forget_window(&closed);
+            let remaining = rt.block_on(driver.windows())?;
+            // Prefer the most recently seen window which is still open
+            let next = state.windows_seen.iter().rev()
+                .find(|h| remaining.contains(h))
+                .or_else(|| remaining.first())
+                .cloned();
+            if let Some(next) = next {
+                state.switch_window(next)?;
+            }
         }
 
-        /// Get element ID
+        /// Wait for a window or tab to open which the flow hasn't switched to or already waited for, for example after clicking a link, and optionally switch to it.
         #[instruction(
-            id = "browser-element-id",
-            lua_name = "GetElementID",
-            name = "Element: Get ID",
+            id = "browser-window-wait-for-new",
+            lua_name = "WaitForNewWindow",
+            name = "Window: Wait for New Window",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_id(
-            element: String,
-        ) -> #[output(id = "id", name = "Element ID")] String {
+        fn window_wait_for_new(
+            #[arg(name = "Switch to New Window")] switch: bool,
+        ) -> #[output(id = "handle", name = "Window Handle")] String {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            let val = rt.block_on(elem.id())?;
-            val.unwrap_or(String::new())
+            let started = std::time::Instant::now();
+            let handle = loop {
+                let handles = rt.block_on(driver.windows())?;
+                if let Some(handle) = handles.iter().find(|h| !state.windows_seen.contains(h)) {
+                    break handle.clone();
+                }
+                if started.elapsed() > state.timeout {
+                    Err(EngineError::NoNewWindow)?;
+                }
+                std::thread::sleep(state.interval);
+            };
+            state.note_window(handle.clone());
+            if switch {
+                state.switch_window(handle.clone())?;
+            }
+            handle.to_string()
         }
 
-        /// Get the HTML within this element's nodes
+        /// Get the position and size of the current window.
         #[instruction(
-            id = "browser-element-inner-html",
-            lua_name = "GetElementInnerHTML",
-            name = "Element: Get Inner HTML",
+            id = "browser-window-get-rect",
+            lua_name = "GetWindowRect",
+            name = "Window: Get Position and Size",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_inner_html(
-            element: String,
-        ) -> #[output(id = "html", name = "Inner HTML")] String {
+        fn window_get_rect() -> (
+            #[output(id = "x", name = "X")] i32,
+            #[output(id = "y", name = "Y")] i32,
+            #[output(id = "width", name = "Width")] i32,
+            #[output(id = "height", name = "Height")] i32,
+        ) {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.inner_html())?
+            let rect = rt.block_on(driver.get_window_rect())?;
+            (
+                i32::try_from(rect.x)?,
+                i32::try_from(rect.y)?,
+                i32::try_from(rect.width)?,
+                i32::try_from(rect.height)?,
+            )
         }
 
-        /// Return is the element is clickable (visible and enabled).
+        /// Set the position and size of the current window.
         #[instruction(
-            id = "browser-element-is-clickable",
-            lua_name = "IsElementClickable",
-            name = "Element: Is Clickable",
+            id = "browser-window-set-rect",
+            lua_name = "SetWindowRect",
+            name = "Window: Set Position and Size",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_is_clickable(
-            element: String,
-        ) -> #[output(id = "clickable", name = "Clickable")] bool {
+        fn window_set_rect(
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+        ) {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.is_clickable())?
+            rt.block_on(driver.set_window_rect(x.into(), y.into(), u32::try_from(width)?, u32::try_from(height)?))?;
         }
 
-        /// Return is the element is displayed.
+        /// Maximize the current window.
         #[instruction(
-            id = "browser-element-is-displayed",
-            lua_name = "IsElementDisplayed",
-            name = "Element: Is Displayed",
+            id = "browser-window-maximize",
+            lua_name = "MaximizeWindow",
+            name = "Window: Maximize",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_is_displayed(
-            element: String,
-        ) -> #[output(id = "displayed", name = "Displayed")] bool {
+        fn window_maximize() {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.is_displayed())?
+            rt.block_on(driver.maximize_window())?;
         }
 
-        /// Return is the element is enabled.
+        /// Minimize the current window.
         #[instruction(
-            id = "browser-element-is-enabled",
-            lua_name = "IsElementEnabled",
-            name = "Element: Is Enabled",
+            id = "browser-window-minimize",
+            lua_name = "MinimizeWindow",
+            name = "Window: Minimize",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_is_enabled(
-            element: String,
-        ) -> #[output(id = "enabled", name = "Enabled")] bool {
+        fn window_minimize() {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.is_enabled())?
+            rt.block_on(driver.minimize_window())?;
         }
 
-        /// Return is the element is selected.
+        /// Make the current window fullscreen.
         #[instruction(
-            id = "browser-element-is-selected",
-            lua_name = "IsElementSelected",
-            name = "Element: Is Selected",
+            id = "browser-window-fullscreen",
+            lua_name = "FullscreenWindow",
+            name = "Window: Fullscreen",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_is_selected(
-            element: String,
-        ) -> #[output(id = "selected", name = "Selected")] bool {
+        fn window_fullscreen() {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.is_selected())?
+            rt.block_on(driver.fullscreen_window())?;
         }
 
-        /// Get the HTML within this element's nodes
+        /// Resize the current window so that the page viewport (excluding the browser's toolbars and borders) is exactly the given size.
         #[instruction(
-            id = "browser-element-outer-html",
-            lua_name = "GetElementOuterHTML",
-            name = "Element: Get Outer HTML",
+            id = "browser-window-set-viewport-size",
+            lua_name = "SetViewportSize",
+            name = "Window: Set Viewport Size",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_outer_html(
-            element: String,
-        ) -> #[output(id = "html", name = "Outer HTML")] String {
+        fn window_set_viewport_size(
+            width: i32,
+            height: i32,
+        ) {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.outer_html())?
+            let target = (i64::from(width), i64::from(height));
+            let viewport_size = || -> WebDriverResult<(i64, i64)> {
+                let ret = rt.block_on(driver.execute("return [window.innerWidth, window.innerHeight];", vec![]))?;
+                ret.convert()
+            };
+
+            // The size of the browser chrome can change as the window is
+            // resized (e.g. wrapping toolbars), so allow a second attempt.
+            for _ in 0..2 {
+                let viewport = viewport_size()?;
+                if viewport == target {
+                    break;
+                }
+                let rect = rt.block_on(driver.get_window_rect())?;
+                let new_width = rect.width + target.0 - viewport.0;
+                let new_height = rect.height + target.1 - viewport.1;
+                rt.block_on(driver.set_window_rect(rect.x, rect.y, u32::try_from(new_width)?, u32::try_from(new_height)?))?;
+            }
+
+            let viewport = viewport_size()?;
+            if viewport != target {
+                Err(EngineError::ViewportSize {
+                    requested: target,
+                    actual: viewport,
+                })?;
+            }
         }
 
-        /// Screenshot an element as evidence
+        /* DOWNLOADS */
+
+        /// Get the directory that files are downloaded to in this session. The directory is kept after the session quits if anything was downloaded to it, and otherwise removed.
         #[instruction(
-            id = "browser-element-screenshot",
-            lua_name = "ScreenshotElementAsEvidence",
-            name = "Element: Screenshot as Evidence",
+            id = "browser-download-dir",
+            lua_name = "GetDownloadDirectory",
+            name = "Downloads: Get Directory",
             flags = InstructionFlags::AUTOMATIC,
         )]
-        fn element_screenshot(
-            element: String,
-            label: String,
+        fn download_dir() -> #[output(id = "path", name = "Directory Path")] String {
+            let dir = state.download_dir.as_ref().ok_or(EngineError::NotInitialised)?;
+            dir.to_string_lossy().to_string()
+        }
+
+        /// Wait for a new download to complete, returning the path to the downloaded file. If the timeout is zero, the session's default timeout is used. Text files and PNG images can be attached as evidence.
+        #[instruction(
+            id = "browser-download-wait",
+            lua_name = "WaitForDownload",
+            name = "Downloads: Wait for Download",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn download_wait(
+            #[arg(name = "Timeout (seconds)")] timeout: i32,
+            #[arg(name = "Attach as Evidence")] attach: bool,
+        ) -> #[output(id = "path", name = "File Path")] String {
+            let dir = state.download_dir.as_ref().ok_or(EngineError::NotInitialised)?;
+            let timeout = match u64::try_from(timeout) {
+                Ok(secs) if secs > 0 => Duration::from_secs(secs),
+                _ => state.timeout,
+            };
+
+            let path = utils::poll(timeout, state.interval, || {
+                let files = std::fs::read_dir(dir)?
+                    .map(|entry| {
+                        let entry = entry?;
+                        let modified = entry.metadata()?.modified().unwrap_or(std::time::UNIX_EPOCH);
+                        Ok((entry.path(), modified))
+                    })
+                    .collect::<Result<Vec<_>, std::io::Error>>()?;
+                Ok::<_, std::io::Error>(downloads::find_completed(&files, &state.downloads_seen))
+            })?
+            .ok_or("No download completed before the timeout.")?;
+            state.downloads_seen.push(path.clone());
+
+            if attach {
+                use base64::{Engine as _, engine::general_purpose};
+
+                let label = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                let data = std::fs::read(&path)?;
+                let content = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+                    EvidenceContent::ImageAsPngBase64(general_purpose::STANDARD.encode(data))
+                } else {
+                    match String::from_utf8(data) {
+                        Ok(text) => EvidenceContent::Textual(text),
+                        Err(e) => EvidenceContent::Textual(format!("{} ({} bytes, binary content not shown)", path.display(), e.as_bytes().len())),
+                    }
+                };
+                evidence.push(Evidence { label, content });
+            }
+            path.to_string_lossy().to_string()
+        }
+
+        /* CHROME DEVTOOLS PROTOCOL */
+
+        /// Execute arbitrary JavaScript.
+        #[instruction(
+            id = "browser-cdp-execute",
+            lua_name = "CDPExecute",
+            name = "Chrome DevTools: Execute",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn cdp_execute(
+            #[arg(name = "Command")] cmd: String,
+        ) -> #[output(id = "return", name = "Return Value as JSON String")] String {
+            use thirtyfour::extensions::cdp::ChromeDevTools;
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let dev_tools = ChromeDevTools::new(driver.handle.clone());
+            let ret = rt.block_on(dev_tools.execute_cdp(&cmd))?;
+            serde_json::to_string(&ret).map_err(|_| "Return value couldn't be converted to JSON string")?
+        }
+
+        /// Direct the browser to a URL.
+        #[instruction(
+            id = "browser-cdp-execute-with-params",
+            lua_name = "CDPExecuteWithParams",
+            name = "Chrome DevTools: Execute with Parameters",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn cdp_execute_with_params(
+            #[arg(name = "Command")] cmd: String,
+            #[arg(name = "Parameter as JSON String")] params: String
+        ) -> #[output(id = "return", name = "Return Value as JSON String")] String {
+            use thirtyfour::extensions::cdp::ChromeDevTools;
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let dev_tools = ChromeDevTools::new(driver.handle.clone());
+            let ret = rt.block_on(dev_tools.execute_cdp_with_params(&cmd, serde_json::from_str(&params).map_err(|_| "Parameters for CDP are not a valid JSON string")?))?;
+            serde_json::to_string(&ret).map_err(|_| "Return value couldn't be converted to JSON string")?
+        }
+
+        /* PAGE INJECTION */
+
+        /// Run a script in every new document, including frames, before the page's own scripts, for example to stub analytics or add test hooks. Returns a handle to remove it with. In Chrome, other windows, such as popups, only run the script in documents loaded after they are switched to.
+        #[instruction(
+            id = "browser-inject-script",
+            lua_name = "InjectScript",
+            name = "Page Injection: Add Script",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn inject_script(
+            #[arg(name = "JavaScript")] script: String,
+        ) -> #[output(id = "handle", name = "Injection Handle")] String {
+            state.inject(&script)?
+        }
+
+        /// Add a stylesheet to every new document, including frames. Returns a handle to remove it with. In Chrome, other windows, such as popups, only get the stylesheet in documents loaded after they are switched to.
+        #[instruction(
+            id = "browser-inject-css",
+            lua_name = "InjectCSS",
+            name = "Page Injection: Add CSS",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn inject_css(
+            #[arg(name = "CSS")] css: String,
+        ) -> #[output(id = "handle", name = "Injection Handle")] String {
+            state.inject(&inject::css_script(&css))?
+        }
+
+        /// Stop injecting a script or stylesheet into new documents. Documents which have already been loaded are not changed. In Chrome, other windows stop once they are next switched to.
+        #[instruction(
+            id = "browser-inject-remove",
+            lua_name = "RemoveInjection",
+            name = "Page Injection: Remove",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn inject_remove(
+            #[arg(name = "Injection Handle")] handle: String,
+        ) {
+            state.uninject(&handle)?;
+            if state.animations_injection.as_deref() == Some(handle.trim()) {
+                state.animations_injection = None;
+                state.emulate_reduced_motion(false)?;
+            }
+        }
+
+        /// Turn CSS animations, transitions and the blinking text caret off (or back on) for this session, so that pages are still while being clicked and screenshotted. Animations finish immediately rather than being removed. The current document is changed straight away, and every new document afterwards, including frames, as with 'Page Injection: Add CSS'. In Chrome, pages in the current window are also told the user prefers reduced motion.
+        #[instruction(
+            id = "browser-disable-animations",
+            lua_name = "DisableAnimations",
+            name = "Page Injection: Disable Animations",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn disable_animations(
+            disable: bool,
+        ) {
+            let css = include_str!("scripts/disable_animations.css");
+            if disable && state.animations_injection.is_none() {
+                let css_script = inject::css_script(css);
+                state.animations_injection = Some(state.inject(&css_script)?);
+                let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+                let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+                rt.block_on(driver.execute(&css_script, vec![]))?;
+            } else if !disable {
+                if let Some(handle) = state.animations_injection.take() {
+                    state.uninject(&handle)?;
+                }
+                let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+                let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+                rt.block_on(driver.execute(include_str!("scripts/remove_css.js"), vec![css.into()]))?;
+            }
+            state.emulate_reduced_motion(disable)?;
+        }
+
+        /* ELEMENT SELECTION */
+
+        /// Select Element By: Alternative text, for example of an image
+        #[instruction(
+            id = "browser-select-by-alt-text",
+            lua_name = "SelectByAltText",
+            name = "Select Element By: Alt Text",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_alt_text(
+            #[arg(id = "alt-text", name = "Alt Text")] alt_text: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let (elem, locator) = state.find_accessible("alt", "", &alt_text)?;
+            state.register_element(&elem, Some(locator))?
+        }
+
+        /// Select Element By: Class Name
+        #[instruction(
+            id = "browser-select-by-class-name",
+            lua_name = "SelectByClassName",
+            name = "Select Element By: Class Name",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_class_name(
+            #[arg(name = "Class Name")] class: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::ClassName(class);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /// Select Element By: CSS Selector
+        #[instruction(
+            id = "browser-select-by-css",
+            lua_name = "SelectByCSS",
+            name = "Select Element By: CSS Selector",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_css(
+            #[arg(name = "CSS Selector")] css: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::Css(css);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /// Select Element By: ID
+        #[instruction(
+            id = "browser-select-by-id",
+            lua_name = "SelectByID",
+            name = "Select Element By: ID",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_id(
+            #[arg(name = "ID")] id: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::Id(id);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /// Select Element By: Text of the label associated with a form control
+        #[instruction(
+            id = "browser-select-by-label",
+            lua_name = "SelectByLabel",
+            name = "Select Element By: Label Text",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_label(
+            #[arg(name = "Label Text")] label: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let (elem, locator) = state.find_accessible("label", "", &label)?;
+            state.register_element(&elem, Some(locator))?
+        }
+
+        /// Select Element By: Link Text
+        #[instruction(
+            id = "browser-select-by-link-text",
+            lua_name = "SelectByLinkText",
+            name = "Select Element By: Link Text",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_link_text(
+            #[arg(id = "link-text", name = "Link Text")] link_text: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::LinkText(link_text);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /// Select Element By: HTML 'name' attribute
+        #[instruction(
+            id = "browser-select-by-name",
+            lua_name = "SelectByName",
+            name = "Select Element By: Name",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_name(
+            #[arg(name = "Name")] name: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::Name(name);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /// Select Element By: Placeholder text of a form control
+        #[instruction(
+            id = "browser-select-by-placeholder",
+            lua_name = "SelectByPlaceholder",
+            name = "Select Element By: Placeholder",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_placeholder(
+            #[arg(name = "Placeholder")] placeholder: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let (elem, locator) = state.find_accessible("placeholder", "", &placeholder)?;
+            state.register_element(&elem, Some(locator))?
+        }
+
+        /// Select Element By: Role and accessible name, for example the "button" called "Submit". If the name is empty, any element with the role matches.
+        #[instruction(
+            id = "browser-select-by-role",
+            lua_name = "SelectByRole",
+            name = "Select Element By: Role and Accessible Name",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_role(
+            role: String,
+            #[arg(id = "accessible-name", name = "Accessible Name")] accessible_name: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let (elem, locator) = state.find_accessible("role", &role, &accessible_name)?;
+            state.register_element(&elem, Some(locator))?
+        }
+
+        /// Select Element By: Tag
+        #[instruction(
+            id = "browser-select-by-tag",
+            lua_name = "SelectByTag",
+            name = "Select Element By: Tag",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_tag(
+            tag: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::Tag(tag);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /// Select Element By: Visible text. The mode is one of "equals", "contains" or "regex". If a tag is given, only elements with that tag are matched. Where nested elements match, the innermost is chosen.
+        #[instruction(
+            id = "browser-select-by-text",
+            lua_name = "SelectByText",
+            name = "Select Element By: Text",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_text(
+            text: String,
+            #[arg(name = "Match Mode")] mode: String,
+            tag: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let mode = mode.to_lowercase();
+            if !["equals", "contains", "regex"].contains(&mode.as_str()) {
+                Err(format!("Unknown match mode {mode:?}, expected \"equals\", \"contains\" or \"regex\"."))?;
+            }
+
+            let script = include_str!("scripts/find_by_text.js");
+            let args = vec![text.clone().into(), mode.into(), tag.into()];
+            let elem = utils::poll(state.timeout, state.interval, || {
+                let ret = rt.block_on(driver.execute(script, args.clone()))?;
+                Ok::<_, WebDriverError>(ret.elements()?.into_iter().next())
+            })?
+            .ok_or_else(|| EngineError::ElementNotFound(format!("with text {text:?}")))?;
+            state.register_element(&elem, Some(Locator::script(script, args, 0)))?
+        }
+
+        /// Select Element By: HTML 'title' attribute
+        #[instruction(
+            id = "browser-select-by-title",
+            lua_name = "SelectByTitle",
+            name = "Select Element By: Title",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_title(
+            title: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let (elem, locator) = state.find_accessible("title", "", &title)?;
+            state.register_element(&elem, Some(locator))?
+        }
+
+        /// Select Element By: XPath
+        #[instruction(
+            id = "browser-select-by-xpath",
+            lua_name = "SelectByXPath",
+            name = "Select Element By: XPath",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_by_xpath(
+            #[arg(name = "XPath")] xpath: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let by = By::XPath(xpath);
+            let elem = rt.block_on(driver.query(by.clone())
+                .wait(state.timeout, state.interval)
+                .first())?;
+            state.register_element(&elem, Some(Locator::by(by)))?
+        }
+
+        /* RELATIVE ELEMENT SELECTION */
+
+        /// Select the closest element matching a CSS selector which is entirely above an anchor element.
+        #[instruction(
+            id = "browser-select-above",
+            lua_name = "SelectAbove",
+            name = "Select Element: Above",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_above(
+            #[arg(name = "Anchor Element")] anchor: String,
+            #[arg(name = "CSS Selector")] css: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let anchor = state.element(&anchor)?;
+            let elem = state.find_relative(&anchor, &css, Relation::Above)?;
+            state.register_element(&elem, None)?
+        }
+
+        /// Select the closest element matching a CSS selector which is entirely below an anchor element.
+        #[instruction(
+            id = "browser-select-below",
+            lua_name = "SelectBelow",
+            name = "Select Element: Below",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_below(
+            #[arg(name = "Anchor Element")] anchor: String,
+            #[arg(name = "CSS Selector")] css: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let anchor = state.element(&anchor)?;
+            let elem = state.find_relative(&anchor, &css, Relation::Below)?;
+            state.register_element(&elem, None)?
+        }
+
+        /// Select the closest element matching a CSS selector which is entirely to the left of an anchor element.
+        #[instruction(
+            id = "browser-select-left-of",
+            lua_name = "SelectLeftOf",
+            name = "Select Element: Left Of",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_left_of(
+            #[arg(name = "Anchor Element")] anchor: String,
+            #[arg(name = "CSS Selector")] css: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let anchor = state.element(&anchor)?;
+            let elem = state.find_relative(&anchor, &css, Relation::LeftOf)?;
+            state.register_element(&elem, None)?
+        }
+
+        /// Select the closest element matching a CSS selector which is entirely to the right of an anchor element.
+        #[instruction(
+            id = "browser-select-right-of",
+            lua_name = "SelectRightOf",
+            name = "Select Element: Right Of",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_right_of(
+            #[arg(name = "Anchor Element")] anchor: String,
+            #[arg(name = "CSS Selector")] css: String,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let anchor = state.element(&anchor)?;
+            let elem = state.find_relative(&anchor, &css, Relation::RightOf)?;
+            state.register_element(&elem, None)?
+        }
+
+        /// Select the closest element matching a CSS selector which is within a distance (in pixels) of an anchor element.
+        #[instruction(
+            id = "browser-select-near",
+            lua_name = "SelectNear",
+            name = "Select Element: Near",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn select_near(
+            #[arg(name = "Anchor Element")] anchor: String,
+            #[arg(name = "CSS Selector")] css: String,
+            #[arg(name = "Distance (px)")] distance: i32,
+        ) -> #[output(id = "element", name = "Element")] String {
+            let anchor = state.element(&anchor)?;
+            let elem = state.find_relative(&anchor, &css, Relation::Near(f64::from(distance)))?;
+            state.register_element(&elem, None)?
+        }
+
+        /* MOUSE */
+
+        /// Release the mouse button, for example after 'Element: Click and Hold'.
+        #[instruction(
+            id = "browser-mouse-release",
+            lua_name = "MouseRelease",
+            name = "Mouse: Release",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn mouse_release() {
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            rt.block_on(driver.action_chain().release().perform())?;
+        }
+
+        /* KEYBOARD */
+
+        /// Press a key or chord of keys, for example "Enter" or "Ctrl+Shift+K", releasing them afterwards. Keys are sent to whichever element has focus.
+        #[instruction(
+            id = "browser-keyboard-press",
+            lua_name = "KeyboardPress",
+            name = "Keyboard: Press",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn keyboard_press(
+            #[arg(name = "Key or Chord")] chord: String,
+        ) {
+            let keys = keys::parse_chord(&chord)?;
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let mut actions = driver.action_chain();
+            for key in &keys {
+                actions = actions.key_down(*key);
+            }
+            for key in keys.iter().rev() {
+                actions = actions.key_up(*key);
+            }
+            rt.block_on(actions.perform())?;
+        }
+
+        /// Hold down a key or chord of keys, for example "Shift", until 'Keyboard: Key Up' is used.
+        #[instruction(
+            id = "browser-keyboard-key-down",
+            lua_name = "KeyboardKeyDown",
+            name = "Keyboard: Key Down",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn keyboard_key_down(
+            #[arg(name = "Key or Chord")] chord: String,
+        ) {
+            let keys = keys::parse_chord(&chord)?;
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let mut actions = driver.action_chain();
+            for key in keys {
+                actions = actions.key_down(key);
+            }
+            rt.block_on(actions.perform())?;
+        }
+
+        /// Release a key or chord of keys held by 'Keyboard: Key Down'.
+        #[instruction(
+            id = "browser-keyboard-key-up",
+            lua_name = "KeyboardKeyUp",
+            name = "Keyboard: Key Up",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn keyboard_key_up(
+            #[arg(name = "Key or Chord")] chord: String,
+        ) {
+            let keys = keys::parse_chord(&chord)?;
+            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+            let mut actions = driver.action_chain();
+            for key in keys.into_iter().rev() {
+                actions = actions.key_up(key);
+            }
+            rt.block_on(actions.perform())?;
+        }
+
+        /* ELEMENT ACTIONS */
+
+        /// Get the accessible name of this element, as computed by the browser where possible.
+        #[instruction(
+            id = "browser-element-accessible-name",
+            lua_name = "GetElementAccessibleName",
+            name = "Element: Get Computed Accessible Name",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_accessible_name(
+            element: String,
+        ) -> #[output(id = "name", name = "Accessible Name")] String {
+            state.with_element(evidence, &element, |elem| async move {
+                match commands::computed_label(&elem).await {
+                    Ok(name) => Ok(name),
+                    Err(_) => Ok(utils::accessibility_fallback(&elem).await?.1),
+                }
+            })?
+        }
+
+        /// Get the role of this element, for example "button" or "textbox", as computed by the browser where possible.
+        #[instruction(
+            id = "browser-element-accessible-role",
+            lua_name = "GetElementAccessibleRole",
+            name = "Element: Get Computed Role",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_accessible_role(
+            element: String,
+        ) -> #[output(id = "role", name = "Role")] String {
+            state.with_element(evidence, &element, |elem| async move {
+                match commands::computed_role(&elem).await {
+                    Ok(role) => Ok(role),
+                    Err(_) => Ok(utils::accessibility_fallback(&elem).await?.0),
+                }
+            })?
+        }
+
+        /// Get attribute
+        #[instruction(
+            id = "browser-element-attr",
+            lua_name = "GetElementAttribute",
+            name = "Element: Get Attribute",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_attr(
+            element: String,
+            #[arg(name = "Attribute Name")] name: String,
+        ) -> #[output(id = "attr", name = "Attribute Value")] String {
+            let name = name.as_str();
+            let val = state.with_element(evidence, &element, |elem| async move { elem.attr(name).await })?;
+            val.unwrap_or(String::new())
+        }
+
+        /// Get class name
+        #[instruction(
+            id = "browser-element-class-name",
+            lua_name = "GetElementClassName",
+            name = "Element: Get Class Name",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_class_name(
+            element: String,
+        ) -> #[output(id = "class", name = "Class Name")] String {
+            let val = state.with_element(evidence, &element, |elem| async move { elem.class_name().await })?;
+            val.unwrap_or(String::new())
+        }
+
+        /// Clear the contents, for example of a text field.
+        #[instruction(
+            id = "browser-element-clear",
+            lua_name = "ClearElement",
+            name = "Element: Clear",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_clear(
+            element: String,
+        ) {
+            state.with_element(evidence, &element, |elem| async move { elem.clear().await })?;
+        }
+
+        /// Click element
+        #[instruction(
+            id = "browser-element-click",
+            lua_name = "ClickElement",
+            name = "Element: Click",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_click(
+            element: String
+        ) {
+            state.with_element(evidence, &element, |elem| async move { elem.click().await })?;
+        }
+
+        /// Press and hold the mouse button on this element, at an offset from its centre. Use 'Mouse: Release' to let go.
+        #[instruction(
+            id = "browser-element-click-and-hold",
+            lua_name = "ClickAndHoldElement",
+            name = "Element: Click and Hold",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_click_and_hold(
+            element: String,
+            #[arg(id = "x-offset", name = "X Offset from Centre")] x_offset: i32,
+            #[arg(id = "y-offset", name = "Y Offset from Centre")] y_offset: i32,
+        ) {
+            let (x_offset, y_offset) = (i64::from(x_offset), i64::from(y_offset));
+            state.with_element(evidence, &element, |elem| async move {
+                elem.handle.action_chain()
+                    .move_to_element_with_offset(&elem, x_offset, y_offset)
+                    .click_and_hold()
+                    .perform()
+                    .await
+            })?;
+        }
+
+        /// Right click this element, at an offset from its centre, for example to open a context menu.
+        #[instruction(
+            id = "browser-element-context-click",
+            lua_name = "ContextClickElement",
+            name = "Element: Context Click (Right Click)",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_context_click(
+            element: String,
+            #[arg(id = "x-offset", name = "X Offset from Centre")] x_offset: i32,
+            #[arg(id = "y-offset", name = "Y Offset from Centre")] y_offset: i32,
+        ) {
+            let (x_offset, y_offset) = (i64::from(x_offset), i64::from(y_offset));
+            state.with_element(evidence, &element, |elem| async move {
+                elem.handle.action_chain()
+                    .move_to_element_with_offset(&elem, x_offset, y_offset)
+                    .context_click()
+                    .perform()
+                    .await
+            })?;
+        }
+
+        /// Get CSS value
+        #[instruction(
+            id = "browser-element-css-value",
+            lua_name = "GetElementCSSValue",
+            name = "Element: Get CSS Value",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_css_value(
+            element: String,
+            #[arg(name = "CSS Property")] name: String,
+        ) -> #[output(id = "value", name = "value")] String {
+            let name = name.as_str();
+            state.with_element(evidence, &element, |elem| async move { elem.css_value(name).await })?
+        }
+
+        /// Double click this element, at an offset from its centre.
+        #[instruction(
+            id = "browser-element-double-click",
+            lua_name = "DoubleClickElement",
+            name = "Element: Double Click",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_double_click(
+            element: String,
+            #[arg(id = "x-offset", name = "X Offset from Centre")] x_offset: i32,
+            #[arg(id = "y-offset", name = "Y Offset from Centre")] y_offset: i32,
+        ) {
+            let (x_offset, y_offset) = (i64::from(x_offset), i64::from(y_offset));
+            state.with_element(evidence, &element, |elem| async move {
+                elem.handle.action_chain()
+                    .move_to_element_with_offset(&elem, x_offset, y_offset)
+                    .double_click()
+                    .perform()
+                    .await
+            })?;
+        }
+
+        /// Drag this element and drop it by an offset, in pixels, from where it started.
+        #[instruction(
+            id = "browser-element-drag-by-offset",
+            lua_name = "DragElementByOffset",
+            name = "Element: Drag by Offset",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_drag_by_offset(
+            element: String,
+            #[arg(id = "x-offset", name = "X Offset")] x_offset: i32,
+            #[arg(id = "y-offset", name = "Y Offset")] y_offset: i32,
+        ) {
+            let (x_offset, y_offset) = (i64::from(x_offset), i64::from(y_offset));
+            state.with_element(evidence, &element, |elem| async move {
+                elem.handle.action_chain()
+                    .drag_and_drop_element_by_offset(&elem, x_offset, y_offset)
+                    .perform()
+                    .await
+            })?;
+        }
+
+        /// Drag this element and drop it onto a target element.
+        #[instruction(
+            id = "browser-element-drag-to-element",
+            lua_name = "DragElementToElement",
+            name = "Element: Drag and Drop to Element",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_drag_to_element(
+            element: String,
+            #[arg(name = "Target Element")] target: String,
+        ) {
+            let target = state.element(&target)?;
+            let target = &target;
+            state.with_element(evidence, &element, |elem| async move {
+                elem.handle.action_chain()
+                    .drag_and_drop_element(&elem, target)
+                    .perform()
+                    .await
+            })?;
+        }
+
+        /// Drag this element and drop it onto a target element by dispatching HTML5 drag and drop events. Use this where 'Element: Drag and Drop to Element' doesn't start a drag, for example with `draggable` elements.
+        #[instruction(
+            id = "browser-element-drag-to-element-html5",
+            lua_name = "DragElementToElementHTML5",
+            name = "Element: Drag and Drop to Element (HTML5 Events)",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_drag_to_element_html5(
+            element: String,
+            #[arg(name = "Target Element")] target: String,
+        ) {
+            let target = state.element(&target)?.to_json()?;
+            let target = &target;
+            let accepted = state.with_element(evidence, &element, |elem| async move {
+                let ret = elem.handle
+                    .execute(include_str!("scripts/drag_html5.js"), vec![elem.to_json()?, target.clone()])
+                    .await?;
+                ret.convert::<bool>()
+            })?;
+            if !accepted {
+                Err("The target element didn't accept the drop.")?;
+            }
+        }
+
+        /// Focus this element using JavaScript
+        #[instruction(
+            id = "browser-element-focus",
+            lua_name = "FocusElement",
+            name = "Element: Focus",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_focus(
+            element: String,
+        ) {
+            state.with_element(evidence, &element, |elem| async move { elem.focus().await })?;
+        }
+
+        /// Move the mouse over this element, at an offset from its centre, for example to show a tooltip.
+        #[instruction(
+            id = "browser-element-hover",
+            lua_name = "HoverElement",
+            name = "Element: Hover",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_hover(
+            element: String,
+            #[arg(id = "x-offset", name = "X Offset from Centre")] x_offset: i32,
+            #[arg(id = "y-offset", name = "Y Offset from Centre")] y_offset: i32,
+        ) {
+            let (x_offset, y_offset) = (i64::from(x_offset), i64::from(y_offset));
+            state.with_element(evidence, &element, |elem| async move {
+                elem.handle.action_chain()
+                    .move_to_element_with_offset(&elem, x_offset, y_offset)
+                    .perform()
+                    .await
+            })?;
+        }
+
+        /// Get element ID
+        #[instruction(
+            id = "browser-element-id",
+            lua_name = "GetElementID",
+            name = "Element: Get ID",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_id(
+            element: String,
+        ) -> #[output(id = "id", name = "Element ID")] String {
+            let val = state.with_element(evidence, &element, |elem| async move { elem.id().await })?;
+            val.unwrap_or(String::new())
+        }
+
+        /// Get the HTML within this element's nodes
+        #[instruction(
+            id = "browser-element-inner-html",
+            lua_name = "GetElementInnerHTML",
+            name = "Element: Get Inner HTML",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_inner_html(
+            element: String,
+        ) -> #[output(id = "html", name = "Inner HTML")] String {
+            state.with_element(evidence, &element, |elem| async move { elem.inner_html().await })?
+        }
+
+        /// Return is the element is clickable (visible and enabled).
+        #[instruction(
+            id = "browser-element-is-clickable",
+            lua_name = "IsElementClickable",
+            name = "Element: Is Clickable",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_is_clickable(
+            element: String,
+        ) -> #[output(id = "clickable", name = "Clickable")] bool {
+            state.with_element(evidence, &element, |elem| async move { elem.is_clickable().await })?
+        }
+
+        /// Return is the element is displayed.
+        #[instruction(
+            id = "browser-element-is-displayed",
+            lua_name = "IsElementDisplayed",
+            name = "Element: Is Displayed",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_is_displayed(
+            element: String,
+        ) -> #[output(id = "displayed", name = "Displayed")] bool {
+            state.with_element(evidence, &element, |elem| async move { elem.is_displayed().await })?
+        }
+
+        /// Return is the element is enabled.
+        #[instruction(
+            id = "browser-element-is-enabled",
+            lua_name = "IsElementEnabled",
+            name = "Element: Is Enabled",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_is_enabled(
+            element: String,
+        ) -> #[output(id = "enabled", name = "Enabled")] bool {
+            state.with_element(evidence, &element, |elem| async move { elem.is_enabled().await })?
+        }
+
+        /// Return is the element is selected.
+        #[instruction(
+            id = "browser-element-is-selected",
+            lua_name = "IsElementSelected",
+            name = "Element: Is Selected",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_is_selected(
+            element: String,
+        ) -> #[output(id = "selected", name = "Selected")] bool {
+            state.with_element(evidence, &element, |elem| async move { elem.is_selected().await })?
+        }
+
+        /// Get the HTML within this element's nodes
+        #[instruction(
+            id = "browser-element-outer-html",
+            lua_name = "GetElementOuterHTML",
+            name = "Element: Get Outer HTML",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_outer_html(
+            element: String,
+        ) -> #[output(id = "html", name = "Outer HTML")] String {
+            state.with_element(evidence, &element, |elem| async move { elem.outer_html().await })?
+        }
+
+        /// Get a DOM property, which may differ from the HTML attribute, for example `value` after typing or `checked` after clicking.
+        #[instruction(
+            id = "browser-element-property",
+            lua_name = "GetElementProperty",
+            name = "Element: Get Property",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_prop(
+            element: String,
+            #[arg(name = "Property Name")] name: String,
+        ) -> #[output(id = "prop", name = "Property Value")] String {
+            let name = name.as_str();
+            let val = state.with_element(evidence, &element, |elem| async move { elem.prop(name).await })?;
+            val.unwrap_or(String::new())
+        }
+
+        /// Get the position and size of this element, in whole pixels relative to the top-left of the page.
+        #[instruction(
+            id = "browser-element-rect",
+            lua_name = "GetElementRect",
+            name = "Element: Get Rect",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_rect(
+            element: String,
+        ) -> (
+            #[output(id = "x", name = "X")] i32,
+            #[output(id = "y", name = "Y")] i32,
+            #[output(id = "width", name = "Width")] i32,
+            #[output(id = "height", name = "Height")] i32,
+        ) {
+            let rect = state.with_element(evidence, &element, |elem| async move { elem.rect().await })?;
+            (
+                utils::round_to_i32(rect.x),
+                utils::round_to_i32(rect.y),
+                utils::round_to_i32(rect.width),
+                utils::round_to_i32(rect.height),
+            )
+        }
+
+        /// Screenshot an element as evidence
+        #[instruction(
+            id = "browser-element-screenshot",
+            lua_name = "ScreenshotElementAsEvidence",
+            name = "Element: Screenshot as Evidence",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_screenshot(
+            element: String,
+            label: String,
         ) {
             use base64::{Engine as _, engine::general_purpose};
 
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-
-            let png_data = rt.block_on(elem.screenshot_as_png())?;
+            let png_data = state.with_element(evidence, &element, |elem| async move { elem.screenshot_as_png().await })?;
             let png_base64 = general_purpose::STANDARD.encode(png_data);
             evidence.push(Evidence { label, content: EvidenceContent::ImageAsPngBase64(png_base64) });
         }
@@ -628,13 +1805,10 @@ engine! {
         fn element_scroll_into_view(
             element: String,
         ) {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.scroll_into_view())?;
+            state.with_element(evidence, &element, |elem| async move { elem.scroll_into_view().await })?;
         }
 
-        /// Send keys (type) to this element. For special keys, see: hpkns.uk/takeys
+        /// Send keys (type) to this element. Special keys can be written in braces, for example `{Enter}`, `{Tab}` or `{Ctrl+A}`. Literal braces must be doubled, as `{{` or `}}`, unless the syntax is turned off with 'Set Special Key Syntax Enabled'.
         #[instruction(
             id = "browser-element-send-keys",
             lua_name = "ElementType",
@@ -645,10 +1819,95 @@ engine! {
             element: String,
             keys: String,
         ) {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.send_keys(keys))?;
+            let keys = state.keys(&keys)?;
+            let keys = keys.as_str();
+            state.with_element(evidence, &element, |elem| async move { elem.send_keys(keys).await })?;
+        }
+
+        /// Check or uncheck this element, for example a checkbox. It is only clicked if it isn't already in the requested state, and it is verified to have changed afterwards.
+        #[instruction(
+            id = "browser-element-set-checked",
+            lua_name = "SetElementChecked",
+            name = "Element: Set Checked",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_set_checked(
+            element: String,
+            checked: bool,
+        ) {
+            let now_checked = state.with_element(evidence, &element, |elem| async move {
+                if elem.is_selected().await? != checked {
+                    elem.click().await?;
+                }
+                elem.is_selected().await
+            })?;
+            if now_checked != checked {
+                Err(format!("The element didn't become {}.", if checked { "checked" } else { "unchecked" }))?;
+            }
+        }
+
+        /// Attach one or more files to a file input (`<input type="file">`), giving each path on a new line. If the browser is running on another machine, the files are uploaded to it first.
+        #[instruction(
+            id = "browser-element-upload-files",
+            lua_name = "UploadFilesToElement",
+            name = "Element: Upload Files",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_upload_files(
+            element: String,
+            #[arg(name = "File Paths")] paths: String,
+        ) {
+            use base64::{Engine as _, engine::general_purpose};
+
+            let paths = paths.lines()
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(|path| {
+                    let absolute = std::path::absolute(path).map_err(|e| format!("Invalid file path {path:?}: {e}"))?;
+                    if absolute.is_file() {
+                        Ok(absolute)
+                    } else {
+                        Err(format!("The file {path:?} doesn't exist."))
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if paths.is_empty() {
+                Err("No files were given to upload.")?;
+            }
+
+            let local_paths = paths.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>().join("\n");
+            let local_paths = local_paths.as_str();
+            match state.with_element(evidence, &element, |elem| async move { elem.send_keys(local_paths).await }) {
+                Err(EngineError::WebDriver(WebDriverError::InvalidArgument(_))) => {
+                    // The driver couldn't find the files, so it is probably
+                    // on another machine. Upload them and try again.
+                    let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+                    let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+                    let mut remote_paths = vec![];
+                    for path in &paths {
+                        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                        let zip = upload::zip_single_file(&name, &std::fs::read(path)?)?;
+                        remote_paths.push(rt.block_on(commands::upload_file(&driver.handle, general_purpose::STANDARD.encode(zip)))?);
+                    }
+                    let remote_paths = remote_paths.join("\n");
+                    let remote_paths = remote_paths.as_str();
+                    state.with_element(evidence, &element, |elem| async move { elem.send_keys(remote_paths).await })?;
+                }
+                result => result?,
+            }
+        }
+
+        /// Get the tag name of this element, for example `input`.
+        #[instruction(
+            id = "browser-element-tag-name",
+            lua_name = "GetElementTagName",
+            name = "Element: Get Tag Name",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_tag_name(
+            element: String,
+        ) -> #[output(id = "tag", name = "Tag Name")] String {
+            state.with_element(evidence, &element, |elem| async move { elem.tag_name().await })?
         }
 
         /// Get the text within this element's nodes
@@ -661,10 +1920,52 @@ engine! {
         fn element_text(
             element: String,
         ) -> #[output(id = "text", name = "Text")] String {
-            let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
-            let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            rt.block_on(elem.text())?
+            state.with_element(evidence, &element, |elem| async move { elem.text().await })?
+        }
+
+        /// Type into this element one key at a time, waiting between each key, for fields which can't keep up with 'Send Keys'. The delay varies randomly by up to the jitter either way. Optionally the field can be cleared first, and its value checked against the text afterwards. Special keys and shortcuts such as `{Ctrl+A}` are left out of the check, while letters typed with Shift, such as `{Shift+a}`, are expected in upper case.
+        #[instruction(
+            id = "browser-element-type-slowly",
+            lua_name = "ElementTypeSlowly",
+            name = "Element: Type Slowly",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn element_type_slowly(
+            element: String,
+            text: String,
+            #[arg(name = "Delay (ms)")] delay: i32,
+            #[arg(name = "Jitter (ms)")] jitter: i32,
+            #[arg(name = "Clear First")] clear: bool,
+            #[arg(name = "Verify Value")] verify: bool,
+        ) {
+            let keystrokes = keys::split_keystrokes(&state.keys(&text)?);
+            let delay = u64::try_from(delay).unwrap_or(0);
+            let jitter = u64::try_from(jitter).unwrap_or(0);
+            let mut rng = utils::Jitter::from_clock();
+
+            if clear {
+                state.with_element(evidence, &element, |elem| async move { elem.clear().await })?;
+            }
+            for (i, keystroke) in keystrokes.iter().enumerate() {
+                if i > 0 {
+                    std::thread::sleep(rng.delay(delay, jitter));
+                }
+                let keystroke = keystroke.as_str();
+                state.with_element(evidence, &element, |elem| async move { elem.send_keys(keystroke).await })?;
+            }
+
+            if verify {
+                let expected = keystrokes.iter()
+                    .map(String::as_str)
+                    .map(keys::typed_text)
+                    .collect::<String>();
+                let value = state.with_element(evidence, &element, |elem| async move { elem.value().await })?;
+                let value = value.unwrap_or_default();
+                let matches = if clear { value == expected } else { value.ends_with(&expected) };
+                if !matches {
+                    Err(format!("The element's value is {value:?}, but {expected:?} was typed."))?;
+                }
+            }
         }
 
         /// Get the value of this element
@@ -677,11 +1978,331 @@ engine! {
         fn element_value(
             element: String,
         ) -> #[output(id = "value", name = "Value")] String {
+            let val = state.with_element(evidence, &element, |elem| async move { elem.value().await })?;
+            val.unwrap_or(String::new())
+        }
+
+        /* DROPDOWNS */
+
+        /// List the options of a dropdown (`<select>` element), as a JSON array of objects with `text`, `value` and `selected` properties.
+        #[instruction(
+            id = "browser-dropdown-list-options",
+            lua_name = "ListDropdownOptions",
+            name = "Dropdown: List Options",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_list_options(
+            element: String,
+        ) -> #[output(id = "options", name = "Options as JSON String")] String {
+            let options = state.with_element(evidence, &element, |elem| async move {
+                let options = SelectElement::new(&elem).await?.options().await?;
+                utils::describe_options(&options).await
+            })?;
+            options.to_string()
+        }
+
+        /// Get the selected options of a dropdown (`<select>` element), as a JSON array of objects with `text` and `value` properties.
+        #[instruction(
+            id = "browser-dropdown-get-selected",
+            lua_name = "GetDropdownSelected",
+            name = "Dropdown: Get Selected Options",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_get_selected(
+            element: String,
+        ) -> #[output(id = "options", name = "Selected Options as JSON String")] String {
+            let mut options = state.with_element(evidence, &element, |elem| async move {
+                let options = SelectElement::new(&elem).await?.all_selected_options().await?;
+                utils::describe_options(&options).await
+            })?;
+            for option in options.as_array_mut().into_iter().flatten() {
+                if let Some(option) = option.as_object_mut() {
+                    option.remove("selected");
+                }
+            }
+            options.to_string()
+        }
+
+        /// Select the option of a dropdown (`<select>` element) with the given visible text.
+        #[instruction(
+            id = "browser-dropdown-select-by-text",
+            lua_name = "SelectDropdownByText",
+            name = "Dropdown: Select by Text",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_select_by_text(
+            element: String,
+            text: String,
+        ) {
+            let text = text.as_str();
+            state.with_element(evidence, &element, |elem| async move {
+                SelectElement::new(&elem).await?.select_by_visible_text(text).await
+            })?;
+        }
+
+        /// Select the option of a dropdown (`<select>` element) with the given value.
+        #[instruction(
+            id = "browser-dropdown-select-by-value",
+            lua_name = "SelectDropdownByValue",
+            name = "Dropdown: Select by Value",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_select_by_value(
+            element: String,
+            value: String,
+        ) {
+            let value = value.as_str();
+            state.with_element(evidence, &element, |elem| async move {
+                SelectElement::new(&elem).await?.select_by_value(value).await
+            })?;
+        }
+
+        /// Select the option of a dropdown (`<select>` element) at the given index, starting at 0.
+        #[instruction(
+            id = "browser-dropdown-select-by-index",
+            lua_name = "SelectDropdownByIndex",
+            name = "Dropdown: Select by Index",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_select_by_index(
+            element: String,
+            index: i32,
+        ) {
+            let index = u32::try_from(index)?;
+            state.with_element(evidence, &element, |elem| async move {
+                SelectElement::new(&elem).await?.select_by_index(index).await
+            })?;
+        }
+
+        /// Deselect the option of a multiple selection dropdown (`<select multiple>` element) with the given visible text.
+        #[instruction(
+            id = "browser-dropdown-deselect-by-text",
+            lua_name = "DeselectDropdownByText",
+            name = "Dropdown: Deselect by Text",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_deselect_by_text(
+            element: String,
+            text: String,
+        ) {
+            let text = text.as_str();
+            state.with_element(evidence, &element, |elem| async move {
+                SelectElement::new(&elem).await?.deselect_by_visible_text(text).await
+            })?;
+        }
+
+        /// Deselect the option of a multiple selection dropdown (`<select multiple>` element) with the given value.
+        #[instruction(
+            id = "browser-dropdown-deselect-by-value",
+            lua_name = "DeselectDropdownByValue",
+            name = "Dropdown: Deselect by Value",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_deselect_by_value(
+            element: String,
+            value: String,
+        ) {
+            let value = value.as_str();
+            state.with_element(evidence, &element, |elem| async move {
+                SelectElement::new(&elem).await?.deselect_by_value(value).await
+            })?;
+        }
+
+        /// Deselect all options of a multiple selection dropdown (`<select multiple>` element).
+        #[instruction(
+            id = "browser-dropdown-deselect-all",
+            lua_name = "DeselectDropdownAll",
+            name = "Dropdown: Deselect All",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn dropdown_deselect_all(
+            element: String,
+        ) {
+            state.with_element(evidence, &element, |elem| async move {
+                SelectElement::new(&elem).await?.deselect_all().await
+            })?;
+        }
+
+        /* RADIO BUTTONS */
+
+        /// Select the radio button with the given value from the group with the given name.
+        #[instruction(
+            id = "browser-radio-select-by-value",
+            lua_name = "SelectRadioByValue",
+            name = "Radio: Select by Value",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn radio_select_by_value(
+            #[arg(name = "Group Name")] name: String,
+            value: String,
+        ) {
             let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
             let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
-            let elem = utils::deserialise_elem(&driver.handle, &element)?;
-            let val = rt.block_on(elem.value())?;
-            val.unwrap_or(String::new())
+            let css = format!(
+                "input[type=radio][name={}][value={}]",
+                utils::css_string(&name),
+                utils::css_string(&value),
+            );
+            let radio = rt.block_on(driver.query(By::Css(css))
+                .wait(state.timeout, state.interval)
+                .first())?;
+            if !rt.block_on(radio.is_selected())? {
+                rt.block_on(radio.click())?;
+            }
+            if !rt.block_on(radio.is_selected())? {
+                Err(format!("The radio button {value:?} in group {name:?} didn't become selected."))?;
+            }
+        }
+
+        /* FORMS */
+
+        /// Fill in the fields of a form from a JSON object, such as `{"email": "a@example.com", "Subscribe": true}`. Each field is found by its name, id or label. Dropdowns are selected by option text or value, checkboxes are checked or unchecked, radio buttons are chosen by value or label, and other fields are cleared and typed into.
+        #[instruction(
+            id = "browser-form-fill",
+            lua_name = "FillForm",
+            name = "Form: Fill",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn form_fill(
+            #[arg(name = "Form")] form: String,
+            #[arg(name = "Fields as JSON Object")] fields: String,
+        ) {
+            let fields: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&fields)
+                .map_err(|e| format!("The fields aren't a valid JSON object: {e}"))?;
+            let script = concat!(
+                include_str!("scripts/accessibility.js"),
+                include_str!("scripts/find_form_field.js"),
+            );
+
+            for (key, value) in &fields {
+                let text = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                let args = vec![key.clone().into(), text.clone().into()];
+                let args = &args;
+                let (field, kind) = state.with_element(evidence, &form, |elem| async move {
+                    let mut args = args.clone();
+                    args.insert(0, elem.to_json()?);
+                    elem.handle.execute(script, args).await?.convert::<(serde_json::Value, String)>()
+                })?;
+                if field.is_null() {
+                    Err(EngineError::ElementNotFound(format!("in the form for the field {key:?} with value {text:?}")))?;
+                }
+
+                let rt = state.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+                let driver = state.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+                let field = WebElement::from_json(field, driver.handle.clone())?;
+                match kind.as_str() {
+                    "select" => rt.block_on(async {
+                        let select = SelectElement::new(&field).await?;
+                        match select.select_by_visible_text(&text).await {
+                            Ok(()) => Ok(()),
+                            Err(_) => select.select_by_value(&text).await,
+                        }
+                    })?,
+                    "checkbox" => {
+                        if rt.block_on(field.is_selected())? != utils::truthy(value)? {
+                            rt.block_on(field.click())?;
+                        }
+                    }
+                    "radio" => {
+                        if !rt.block_on(field.is_selected())? {
+                            rt.block_on(field.click())?;
+                        }
+                    }
+                    _ => {
+                        rt.block_on(field.clear())?;
+                        rt.block_on(field.send_keys(&text))?;
+                    }
+                }
+            }
+        }
+
+        /// Submit the form containing this element, as if the user had submitted it, so its validation and event handlers run. The element may be the form itself, a field within it, or a submit button, which is then reported as the button that submitted the form.
+        #[instruction(
+            id = "browser-form-submit",
+            lua_name = "SubmitForm",
+            name = "Form: Submit",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn form_submit(
+            element: String,
+        ) {
+            let submitted = state.with_element(evidence, &element, |elem| async move {
+                let ret = elem.handle
+                    .execute(include_str!("scripts/submit_form.js"), vec![elem.to_json()?])
+                    .await?;
+                ret.convert::<bool>()
+            })?;
+            if !submitted {
+                Err("The element isn't part of a form.")?;
+            }
+        }
+
+        /* TABLES */
+
+        /// Extract the contents of a table (a `<table>`, or an element with the ARIA role "table" or "grid") as a JSON array with an object for each row, keyed by column header. Cells spanning several rows or columns are repeated in each. The table can also be attached as CSV evidence.
+        #[instruction(
+            id = "browser-table-extract",
+            lua_name = "ExtractTable",
+            name = "Table: Extract",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn table_extract(
+            table: String,
+            #[arg(name = "Attach as CSV Evidence")] attach_csv: bool,
+        ) -> #[output(id = "rows", name = "Rows as JSON Array")] String {
+            let (headers, rows) = state.with_element(evidence, &table, |elem| async move {
+                let script = concat!(
+                    include_str!("scripts/accessibility.js"),
+                    include_str!("scripts/table.js"),
+                    include_str!("scripts/extract_table.js"),
+                );
+                let ret = elem.handle.execute(script, vec![elem.to_json()?]).await?;
+                ret.convert::<(Vec<String>, Vec<Vec<String>>)>()
+            })?;
+            if attach_csv {
+                evidence.push(Evidence {
+                    label: format!("Table `{table}`"),
+                    content: EvidenceContent::Textual(table::to_csv(&headers, &rows)),
+                });
+            }
+            table::to_json(&headers, &rows).to_string()
+        }
+
+        /// Select the row of a table whose cell under the given column header has the given value, for example the row where "Invoice" is "1042". Column headers are written as in the output of 'Table: Extract', such as "Total (2)" for the second "Total" column.
+        #[instruction(
+            id = "browser-table-select-row",
+            lua_name = "SelectTableRow",
+            name = "Table: Select Row by Column Value",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn table_select_row(
+            table: String,
+            #[arg(name = "Column Header")] header: String,
+            value: String,
+        ) -> #[output(id = "element", name = "Row")] String {
+            let (elem, locator) = state.find_in_table(&table, &header, &value, None)?;
+            state.register_element(&elem, Some(locator))?
+        }
+
+        /// Select a cell of a table, in the column with the given header and the row whose cell under the match column header has the match value. Column headers are written as in the output of 'Table: Extract'.
+        #[instruction(
+            id = "browser-table-select-cell",
+            lua_name = "SelectTableCell",
+            name = "Table: Select Cell",
+            flags = InstructionFlags::AUTOMATIC,
+        )]
+        fn table_select_cell(
+            table: String,
+            #[arg(name = "Match Column Header")] match_header: String,
+            #[arg(name = "Match Value")] value: String,
+            #[arg(name = "Column Header")] header: String,
+        ) -> #[output(id = "element", name = "Cell")] String {
+            let (elem, locator) = state.find_in_table(&table, &match_header, &value, Some(&header))?;
+            state.register_element(&elem, Some(locator))?
         }
     }
 }
@@ -694,7 +2315,577 @@ impl Default for Browser {
             child_driver: None,
             timeout: Duration::from_secs(10),
             interval: Duration::from_millis(100),
+            frame: vec![],
+            windows_seen: vec![],
+            elements: ElementRegistry::default(),
+            browser: None,
+            download_dir: None,
+            downloads_seen: vec![],
+            parse_key_syntax: true,
+            injections: Injections::default(),
+            animations_injection: None,
+            bidi: None,
+        }
+    }
+}
+
+impl Browser {
+    /// Translate the special key syntax in `text` into the keys to send, if
+    /// it hasn't been turned off for this session.
+    fn keys(&self, text: &str) -> Result<String, String> {
+        if self.parse_key_syntax {
+            keys::parse_key_syntax(text)
+        } else {
+            Ok(text.to_string())
+        }
+    }
+
+    /// Get the element referred to by a handle. The legacy form of handles,
+    /// the element's `WebDriver` JSON, is also accepted.
+    fn element(&self, handle: &str) -> Result<WebElement, EngineError> {
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        if handle.trim_start().starts_with('{') {
+            return utils::deserialise_elem(&driver.handle, handle, &self.frame);
+        }
+        let session = driver.handle.session_id().to_string();
+        let json = self.elements.lookup(handle, &session, &self.frame)?;
+        Ok(WebElement::from_json(json, driver.handle.clone())?)
+    }
+
+    /// Register an element found in the current frame, returning a handle
+    /// which can be passed to other instructions. If a locator is given, the
+    /// element can be found again should it go stale.
+    fn register_element(
+        &mut self,
+        elem: &WebElement,
+        locator: Option<Locator>,
+    ) -> Result<String, EngineError> {
+        let session = elem.handle.session_id().to_string();
+        Ok(self
+            .elements
+            .register(elem.to_json()?, session, self.frame.clone(), locator))
+    }
+
+    /// Register `script` to run in every new document, before the page's own
+    /// scripts, returning its handle. Firefox runs it in every window through
+    /// `WebDriver BiDi`. Chrome `DevTools` only registers scripts in a single
+    /// window, so they are registered in each window as it is switched to.
+    fn inject(&mut self, script: &str) -> Result<String, EngineError> {
+        let (handle, source) = self.injections.prepare(script);
+        let preload_script = if self.browser == Some(BrowserKind::Firefox) {
+            let function = format!("() => {{\n{source}\n}}");
+            let ret = self.bidi_command(
+                "script.addPreloadScript",
+                serde_json::json!({ "functionDeclaration": function }),
+            )?;
+            let script = ret["script"].as_str().ok_or_else(|| {
+                EngineError::BiDi(format!("Firefox didn't register the script: {ret}"))
+            })?;
+            Some(script.to_string())
+        } else {
+            None
+        };
+        self.injections.add(Injection {
+            handle: handle.clone(),
+            source,
+            preload_script,
+        });
+        if let Err(e) = self.sync_injections(None) {
+            self.injections.remove(&handle);
+            return Err(e);
+        }
+        Ok(handle)
+    }
+
+    /// Stop injecting the script with the handle `handle` into new documents.
+    /// With Chrome, it stops in other windows when they are next switched to.
+    fn uninject(&mut self, handle: &str) -> Result<(), EngineError> {
+        let injection = self
+            .injections
+            .remove(handle)
+            .ok_or_else(|| EngineError::UnknownInjection(handle.to_string()))?;
+        if let Some(script) = injection.preload_script {
+            self.bidi_command(
+                "script.removePreloadScript",
+                serde_json::json!({ "script": script }),
+            )?;
+        }
+        self.sync_injections(None)
+    }
+
+    /// Bring the scripts registered with Chrome `DevTools` in a window up to
+    /// date with the session's injections. The window must be the current
+    /// one, which is looked up if not given.
+    fn sync_injections(&mut self, window: Option<&WindowHandle>) -> Result<(), EngineError> {
+        use thirtyfour::extensions::cdp::ChromeDevTools;
+        if self.browser != Some(BrowserKind::Chrome) {
+            return Ok(());
+        }
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let window = match window {
+            Some(window) => window.clone(),
+            None => rt.block_on(driver.window())?,
+        };
+        let dev_tools = ChromeDevTools::new(driver.handle.clone());
+
+        for registration in self.injections.removed_from(&window) {
+            rt.block_on(dev_tools.execute_cdp_with_params(
+                "Page.removeScriptToEvaluateOnNewDocument",
+                serde_json::json!({ "identifier": registration.identifier }),
+            ))?;
+            self.injections.unregister(&window, &registration.handle);
+        }
+
+        let missing = self
+            .injections
+            .missing_from(&window)
+            .into_iter()
+            .map(|injection| (injection.handle.clone(), injection.source.clone()))
+            .collect::<Vec<_>>();
+        for (handle, source) in missing {
+            let ret = rt.block_on(dev_tools.execute_cdp_with_params(
+                "Page.addScriptToEvaluateOnNewDocument",
+                serde_json::json!({ "source": source }),
+            ))?;
+            let identifier = ret["identifier"].as_str().ok_or_else(|| {
+                EngineError::ScriptFailed(format!("Chrome didn't register the script: {ret}"))
+            })?;
+            self.injections.register(WindowRegistration {
+                window: window.clone(),
+                handle,
+                identifier: identifier.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Tell pages in the current window whether the user prefers reduced
+    /// motion. Only Chrome can emulate this, through `DevTools`, so other
+    /// browsers are left as they are.
+    fn emulate_reduced_motion(&self, reduce: bool) -> Result<(), EngineError> {
+        use thirtyfour::extensions::cdp::ChromeDevTools;
+        if self.browser != Some(BrowserKind::Chrome) {
+            return Ok(());
+        }
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let dev_tools = ChromeDevTools::new(driver.handle.clone());
+        let features = if reduce {
+            serde_json::json!([{ "name": "prefers-reduced-motion", "value": "reduce" }])
+        } else {
+            serde_json::json!([])
+        };
+        rt.block_on(dev_tools.execute_cdp_with_params(
+            "Emulation.setEmulatedMedia",
+            serde_json::json!({ "features": features }),
+        ))?;
+        Ok(())
+    }
+
+    /// Run a `WebDriver BiDi` command, connecting to the session's socket
+    /// first if needed. Firefox serves it from its remote agent, on the port
+    /// given by `TA_BROWSER_BIDI_PORT` or the default of 9222.
+    fn bidi_command(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        if self.bidi.is_none() {
+            let port = std::env::var("TA_BROWSER_BIDI_PORT").unwrap_or("9222".to_string());
+            let url = format!(
+                "ws://127.0.0.1:{port}/session/{}",
+                driver.handle.session_id()
+            );
+            let connection = rt
+                .block_on(bidi::BiDi::connect(&url))
+                .map_err(EngineError::BiDi)?;
+            self.bidi = Some(connection);
+        }
+        let bidi = self.bidi.as_mut().ok_or(EngineError::NotInitialised)?;
+        rt.block_on(bidi.command(method, params))
+            .map_err(EngineError::BiDi)
+    }
+
+    /// Parse a JSON array of arguments for a script, replacing element handles
+    /// with the elements they refer to.
+    fn script_args(&self, args: &str) -> Result<Vec<serde_json::Value>, EngineError> {
+        let args = if args.trim().is_empty() { "[]" } else { args };
+        let args: Vec<serde_json::Value> = serde_json::from_str(args)
+            .map_err(|e| EngineError::InvalidScriptArguments(e.to_string()))?;
+        args.into_iter()
+            .map(|arg| {
+                utils::map_json::<EngineError, _>(arg, &mut |value| match value {
+                    serde_json::Value::String(handle) if self.elements.contains(handle) => {
+                        Ok(Some(self.element(handle)?.to_json()?))
+                    }
+                    _ => Ok(None),
+                })
+            })
+            .collect()
+    }
+
+    /// Replace any elements returned from a script with new element handles,
+    /// also returning the first of them.
+    fn script_return(
+        &mut self,
+        ret: serde_json::Value,
+    ) -> Result<(serde_json::Value, Option<String>), EngineError> {
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let session = driver.handle.clone();
+        let mut first = None;
+        let ret = utils::map_json(ret, &mut |value| {
+            if !utils::is_element(value) {
+                return Ok(None);
+            }
+            let elem = WebElement::from_json(value.clone(), session.clone())?;
+            let handle = self.register_element(&elem, None)?;
+            first.get_or_insert_with(|| handle.clone());
+            Ok::<_, EngineError>(Some(handle.into()))
+        })?;
+        Ok((ret, first))
+    }
+
+    /// Find the first row of the table behind the handle `table` whose cell
+    /// under `match_header` has the value `value`, waiting for one to appear.
+    /// If `header` is given, the row's cell under that header is found
+    /// instead. Headers are keys as given by `table::header_keys`.
+    fn find_in_table(
+        &self,
+        table: &str,
+        match_header: &str,
+        value: &str,
+        header: Option<&str>,
+    ) -> Result<(WebElement, Locator), EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let script = concat!(
+            include_str!("scripts/accessibility.js"),
+            include_str!("scripts/table.js"),
+            include_str!("scripts/find_table_cells.js"),
+        );
+        let table_elem = self.element(table)?;
+
+        let headers_script = concat!(
+            include_str!("scripts/accessibility.js"),
+            include_str!("scripts/table.js"),
+            "return taTableGrid(arguments[0]).headers;",
+        );
+        let headers: Vec<String> = rt
+            .block_on(driver.execute(headers_script, vec![table_elem.to_json()?]))?
+            .convert()?;
+        let column = |header: &str| {
+            table::column_index(&headers, header).ok_or_else(|| EngineError::ColumnNotFound {
+                header: header.to_string(),
+                headers: table::header_keys(&headers),
+            })
+        };
+        let args = vec![
+            column(match_header)?.into(),
+            value.into(),
+            header.map(column).transpose()?.into(),
+        ];
+        let description = match header {
+            Some(header) => format!("in column {header:?} where {match_header:?} is {value:?}"),
+            None => format!("where {match_header:?} is {value:?}"),
+        };
+
+        let mut scoped_args = args.clone();
+        scoped_args.insert(0, table_elem.to_json()?);
+
+        let found = utils::poll(self.timeout, self.interval, || {
+            let ret = rt.block_on(driver.execute(script, scoped_args.clone()))?;
+            let candidates: Vec<serde_json::Value> = ret.convert()?;
+            candidates
+                .into_iter()
+                .next()
+                .map(|elem| WebElement::from_json(elem, driver.handle.clone()))
+                .transpose()
+                .map_err(EngineError::from)
+        })?;
+        let elem = found.ok_or_else(|| {
+            EngineError::ElementNotFound(format!("in the table `{table}` {description}"))
+        })?;
+        Ok((elem, Locator::script(script, args, 0).within(table)))
+    }
+
+    /// Find the element behind a handle again, using the locator which
+    /// originally found it. Returns `None` if the element has no locator or
+    /// can no longer be found. A stale scope, such as a table which has been
+    /// rendered again, is found again first.
+    fn relocate(&mut self, handle: &str) -> Result<Option<WebElement>, EngineError> {
+        let Some(locator) = self.elements.locator(handle) else {
+            return Ok(None);
+        };
+        let scope = locator
+            .scope
+            .as_deref()
+            .map(|scope| self.current_element(scope))
+            .transpose()?;
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+
+        match locator.strategy {
+            Strategy::By(by) => {
+                let candidates = match &scope {
+                    Some(scope) => rt.block_on(scope.find_all(by))?,
+                    None => rt.block_on(driver.find_all(by))?,
+                };
+                Ok(candidates.into_iter().nth(locator.index))
+            }
+            Strategy::Script { script, mut args } => {
+                if let Some(scope) = &scope {
+                    args.insert(0, scope.to_json()?);
+                }
+                let ret = rt.block_on(driver.execute(script, args))?;
+                let candidates = ret.json().as_array().cloned().unwrap_or_default();
+                let mut matching = vec![];
+                for candidate in candidates {
+                    let elem = candidate.get(0).unwrap_or(&candidate).clone();
+                    let elem = WebElement::from_json(elem, driver.handle.clone())?;
+                    if let Some(name) = &locator.name {
+                        let js_name = candidate
+                            .get(2)
+                            .and_then(|n| n.as_str())
+                            .unwrap_or_default();
+                        if self.accessible_name(&elem, js_name.to_string()) != *name {
+                            continue;
+                        }
+                    }
+                    matching.push(elem);
+                }
+                Ok(matching.into_iter().nth(locator.index))
+            }
+        }
+    }
+
+    /// Get the element behind a handle, finding it again using its locator
+    /// if it has gone stale.
+    fn current_element(&mut self, handle: &str) -> Result<WebElement, EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let elem = self.element(handle)?;
+        if rt.block_on(elem.is_present())? {
+            return Ok(elem);
+        }
+        let elem = self
+            .relocate(handle)?
+            .ok_or_else(|| EngineError::StaleElement(handle.to_string()))?;
+        self.elements.update(handle, elem.to_json()?);
+        Ok(elem)
+    }
+
+    /// Run `f` with the element referred to by a handle. If the element has
+    /// gone stale, it is found again using its locator and `f` is retried
+    /// once, recording a warning as evidence. Elements without a locator, such
+    /// as those from the relative selectors or returned by scripts, can't be
+    /// found again and report that they are stale.
+    fn with_element<T, F, Fut>(
+        &mut self,
+        evidence: &mut Vec<Evidence>,
+        handle: &str,
+        f: F,
+    ) -> Result<T, EngineError>
+    where
+        F: Fn(WebElement) -> Fut,
+        Fut: std::future::Future<Output = WebDriverResult<T>>,
+    {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let explain = |e: WebDriverError| match e {
+            WebDriverError::StaleElementReference(_) => {
+                EngineError::StaleElement(handle.to_string())
+            }
+            e => e.into(),
+        };
+
+        let elem = self.element(handle)?;
+        match rt.block_on(f(elem)) {
+            Err(WebDriverError::StaleElementReference(_)) => {
+                let elem = self
+                    .relocate(handle)?
+                    .ok_or_else(|| EngineError::StaleElement(handle.to_string()))?;
+                self.elements.update(handle, elem.to_json()?);
+                evidence.push(Evidence {
+                    label: format!("Warning: element `{handle}` was stale"),
+                    content: EvidenceContent::Textual(format!(
+                        "The element `{handle}` had been removed from the page, so it was found again using the locator which originally selected it."
+                    )),
+                });
+                let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+                rt.block_on(f(elem)).map_err(explain)
+            }
+            result => result.map_err(explain),
+        }
+    }
+
+    /// Enter the frame given by `elem` and record it in the frame context.
+    fn enter_frame(&mut self, elem: WebElement) -> Result<(), EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let descriptor = rt.block_on(async {
+            let tag = elem.tag_name().await?;
+            let label = match (elem.attr("name").await?, elem.id().await?) {
+                (Some(name), _) if !name.is_empty() => format!("{tag} {name:?}"),
+                (_, Some(id)) if !id.is_empty() => format!("{tag} #{id}"),
+                _ => tag,
+            };
+            Ok::<_, WebDriverError>(format!("{label} ({})", elem.element_id()))
+        })?;
+        rt.block_on(elem.enter_frame())?;
+        self.frame.push(descriptor);
+        Ok(())
+    }
+
+    /// Find the first element matching an accessibility query, waiting for it
+    /// to appear, along with a locator to find it again. See
+    /// `scripts/find_accessible.js` for the kinds of query.
+    fn find_accessible(
+        &self,
+        kind: &str,
+        role: &str,
+        text: &str,
+    ) -> Result<(WebElement, Locator), EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let text = utils::normalise_whitespace(text);
+        let args = vec![kind.into(), role.into(), text.clone().into()];
+        let script = concat!(
+            include_str!("scripts/accessibility.js"),
+            include_str!("scripts/find_accessible.js"),
+        );
+
+        let found = utils::poll(self.timeout, self.interval, || {
+            let ret = rt.block_on(driver.execute(script, args.clone()))?;
+            let candidates: Vec<(serde_json::Value, String, String)> = ret.convert()?;
+            for (index, (elem, _role, js_name)) in candidates.into_iter().enumerate() {
+                let elem = WebElement::from_json(elem, driver.handle.clone())?;
+                if kind != "role" || text.is_empty() {
+                    return Ok(Some((elem, Locator::script(script, args.clone(), index))));
+                }
+                if self.accessible_name(&elem, js_name) == text {
+                    // The name is checked again when relocating, so this is
+                    // the first candidate with it
+                    let locator = Locator::script(script, args.clone(), 0).with_name(text.clone());
+                    return Ok(Some((elem, locator)));
+                }
+            }
+            Ok::<_, EngineError>(None)
+        })?;
+
+        found.ok_or_else(|| {
+            EngineError::ElementNotFound(match kind {
+                "role" if text.is_empty() => format!("with role {role:?}"),
+                "role" => format!("with role {role:?} and name {text:?}"),
+                _ => format!("with {kind} {text:?}"),
+            })
+        })
+    }
+
+    /// Get the accessible name of an element, preferring the browser's own
+    /// computation where it is available over `js_name`, the name computed
+    /// by `scripts/accessibility.js`.
+    fn accessible_name(&self, elem: &WebElement, js_name: String) -> String {
+        match &self.rt {
+            Some(rt) => rt
+                .block_on(commands::computed_label(elem))
+                .map_or(js_name, utils::normalise_whitespace),
+            None => js_name,
+        }
+    }
+
+    /// Find the element matching `css` which is closest to `anchor` while
+    /// being in the given relation to it, waiting for one to appear.
+    fn find_relative(
+        &self,
+        anchor: &WebElement,
+        css: &str,
+        relation: Relation,
+    ) -> Result<WebElement, EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let anchor_bounds = rt.block_on(anchor.rect())?.into();
+
+        let found = utils::poll(self.timeout, self.interval, || {
+            let candidates: Vec<WebElement> = rt
+                .block_on(driver.find_all(By::Css(css)))?
+                .into_iter()
+                .filter(|candidate| candidate.element_id() != anchor.element_id())
+                .collect();
+            let bounds = rt.block_on(async {
+                let mut bounds: Vec<relative::Bounds> = Vec::with_capacity(candidates.len());
+                for candidate in &candidates {
+                    bounds.push(candidate.rect().await?.into());
+                }
+                Ok::<_, WebDriverError>(bounds)
+            })?;
+            let closest = relative::rank(&anchor_bounds, &bounds, relation)
+                .first()
+                .map(|&i| candidates[i].clone());
+            Ok::<_, WebDriverError>(closest)
+        })?;
+
+        found.ok_or_else(|| EngineError::ElementNotFound(format!("matching {css:?} {relation}")))
+    }
+
+    /// Record a window as seen, once the flow has switched to it, so that
+    /// 'Wait for New Window' no longer treats it as new. Windows which have
+    /// only been listed or looked at aren't recorded.
+    fn note_window(&mut self, handle: WindowHandle) {
+        if !self.windows_seen.contains(&handle) {
+            self.windows_seen.push(handle);
+        }
+    }
+
+    /// Switch to a window, which always returns to its top-level document.
+    fn switch_window(&mut self, handle: WindowHandle) -> Result<(), EngineError> {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        rt.block_on(driver.switch_to_window(handle.clone()))?;
+        self.frame.clear();
+        self.sync_injections(Some(&handle))?;
+        self.note_window(handle);
+        Ok(())
+    }
+
+    /// Switch to the first window for which `matches` returns true. If no
+    /// window matches, or checking a window fails, the original window is
+    /// restored. Either way the browser is left in a top-level document.
+    fn switch_window_matching<F, Fut>(
+        &mut self,
+        description: &str,
+        matches: F,
+    ) -> Result<(), EngineError>
+    where
+        F: Fn(Arc<SessionHandle>) -> Fut,
+        Fut: std::future::Future<Output = WebDriverResult<bool>>,
+    {
+        let rt = self.rt.as_ref().ok_or(EngineError::NotInitialised)?;
+        let driver = self.driver.as_ref().ok_or(EngineError::NotInitialised)?;
+        let original = rt.block_on(driver.window())?;
+        let handles = rt.block_on(driver.windows())?;
+        self.frame.clear();
+        for handle in handles {
+            let matched = rt.block_on(async {
+                driver.switch_to_window(handle.clone()).await?;
+                matches(driver.handle.clone()).await
+            });
+            match matched {
+                Ok(true) => {
+                    self.sync_injections(Some(&handle))?;
+                    self.note_window(handle);
+                    return Ok(());
+                }
+                Ok(false) => (),
+                Err(e) => {
+                    // The original window may have closed too, so only the
+                    // first error is reported.
+                    let _ = rt.block_on(driver.switch_to_window(original));
+                    return Err(e.into());
+                }
+            }
         }
+        rt.block_on(driver.switch_to_window(original))?;
+        Err(EngineError::WindowNotFound(description.to_string()))
     }
 }
 
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use thirtyfour::By;
+
+use crate::{utils, EngineError};
+
+/// How an element was originally found, so that it can be found again if it
+/// goes stale.
+#[derive(Clone, Debug)]
+pub struct Locator {
+    pub strategy: Strategy,
+    /// The handle of the element that the search was made within.
+    pub scope: Option<String>,
+    /// The accessible name which candidates must have, if any. Candidates
+    /// without it are skipped before `index` is applied.
+    pub name: Option<String>,
+    /// Which of the matching elements was chosen.
+    pub index: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum Strategy {
+    /// A `WebDriver` locator strategy.
+    By(By),
+    /// A script returning an array of candidates, each either an element or an
+    /// array starting with an element. If there is a scope, it is passed to the
+    /// script before `args`.
+    Script {
+        script: &'static str,
+        args: Vec<Value>,
+    },
+}
+
+impl Locator {
+    /// Locate the first element matching `by`.
+    pub fn by(by: By) -> Self {
+        Self {
+            strategy: Strategy::By(by),
+            scope: None,
+            name: None,
+            index: 0,
+        }
+    }
+
+    /// Locate the element at `index` in the candidates returned by `script`.
+    pub fn script(script: &'static str, args: Vec<Value>, index: usize) -> Self {
+        Self {
+            strategy: Strategy::Script { script, args },
+            scope: None,
+            name: None,
+            index,
+        }
+    }
+
+    /// Only count candidates with the accessible name `name`.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Search within the element behind the handle `scope`.
+    pub fn within(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+}
+
+/// An element which has been handed out to a flow.
+struct Entry {
+    /// The `WebDriver` session the element was found in.
+    session: String,
+    /// The frame context the element was found in.
+    frame: Vec<String>,
+    /// The `WebDriver` JSON representation of the element.
+    element: Value,
+    /// How the element was found, if it can be found again.
+    locator: Option<Locator>,
+}
+
+/// Hands out short, opaque handles for elements and keeps track of where each
+/// one came from, so that misuse can be reported clearly.
+#[derive(Default)]
+pub struct ElementRegistry {
+    next: usize,
+    entries: HashMap<String, Entry>,
+}
+
+impl ElementRegistry {
+    /// Register an element, returning a new handle for it.
+    pub fn register(
+        &mut self,
+        element: Value,
+        session: String,
+        frame: Vec<String>,
+        locator: Option<Locator>,
+    ) -> String {
+        self.next += 1;
+        let handle = format!("el-{}", self.next);
+        self.entries.insert(
+            handle.clone(),
+            Entry {
+                session,
+                frame,
+                element,
+                locator,
+            },
+        );
+        handle
+    }
+
+    /// Look up the `WebDriver` JSON for an element handle, checking that it is
+    /// usable in the current session and frame.
+    pub fn lookup(
+        &self,
+        handle: &str,
+        session: &str,
+        frame: &[String],
+    ) -> Result<Value, EngineError> {
+        let entry = self
+            .entries
+            .get(handle.trim())
+            .ok_or_else(|| EngineError::UnknownElement(handle.to_string()))?;
+        if entry.session != session {
+            return Err(EngineError::ElementFromOtherSession(handle.to_string()));
+        }
+        if entry.frame != frame {
+            return Err(EngineError::WrongFrame {
+                element: utils::describe_frame(&entry.frame),
+                current: utils::describe_frame(frame),
+            });
+        }
+        Ok(entry.element.clone())
+    }
+
+    /// Returns true if `handle` is a handle given out by this registry.
+    pub fn contains(&self, handle: &str) -> bool {
+        self.entries.contains_key(handle.trim())
+    }
+
+    /// Get the locator which originally found the element behind a handle.
+    pub fn locator(&self, handle: &str) -> Option<Locator> {
+        self.entries
+            .get(handle.trim())
+            .and_then(|entry| entry.locator.clone())
+    }
+
+    /// Replace the element behind a handle, for example once it has been
+    /// found again after going stale.
+    pub fn update(&mut self, handle: &str, element: Value) {
+        if let Some(entry) = self.entries.get_mut(handle.trim()) {
+            entry.element = element;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use thirtyfour::By;
+
+    use super::{ElementRegistry, Locator};
+    use crate::EngineError;
+
+    #[test]
+    fn test_registry_lookup() {
+        let mut registry = ElementRegistry::default();
+        let frame = vec!["iframe #payment".to_string()];
+        let a = registry.register(json!({ "id": "a" }), "s1".to_string(), vec![], None);
+        let b = registry.register(json!({ "id": "b" }), "s1".to_string(), frame.clone(), None);
+        assert_ne!(a, b);
+        assert!(registry.contains(&a));
+        assert!(!registry.contains("el-999"));
+
+        assert_eq!(
+            registry.lookup(&a, "s1", &[]).unwrap(),
+            json!({ "id": "a" })
+        );
+        assert_eq!(
+            registry.lookup(&b, "s1", &frame).unwrap(),
+            json!({ "id": "b" })
+        );
+        assert!(matches!(
+            registry.lookup("el-999", "s1", &[]),
+            Err(EngineError::UnknownElement(_))
+        ));
+        assert!(matches!(
+            registry.lookup(&a, "s2", &[]),
+            Err(EngineError::ElementFromOtherSession(_))
+        ));
+        assert!(matches!(
+            registry.lookup(&b, "s1", &[]),
+            Err(EngineError::WrongFrame { .. })
+        ));
+    }
+
+    #[test]
+    fn test_registry_update() {
+        let mut registry = ElementRegistry::default();
+        let a = registry.register(json!({ "id": "a" }), "s1".to_string(), vec![], None);
+        assert!(registry.locator(&a).is_none());
+        registry.update(&a, json!({ "id": "a2" }));
+        assert_eq!(
+            registry.lookup(&a, "s1", &[]).unwrap(),
+            json!({ "id": "a2" })
+        );
+    }
+
+    #[test]
+    fn test_registry_scoped_locator() {
+        let mut registry = ElementRegistry::default();
+        let table = registry.register(json!({ "id": "t" }), "s1".to_string(), vec![], None);
+        let locator = Locator::by(By::Css("tr".to_string())).within(&table);
+        let row = registry.register(
+            json!({ "id": "r" }),
+            "s1".to_string(),
+            vec![],
+            Some(locator),
+        );
+        assert_eq!(registry.locator(&row).unwrap().scope, Some(table));
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+use thirtyfour::ElementRect;
+
+/// The bounding rectangle of an element on the page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Bounds {
+    fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    fn centre(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    /// The shortest distance between the edges of two rectangles, or zero if
+    /// they overlap.
+    fn gap(&self, other: &Bounds) -> f64 {
+        let dx = (other.x - self.right())
+            .max(self.x - other.right())
+            .max(0.0);
+        let dy = (other.y - self.bottom())
+            .max(self.y - other.bottom())
+            .max(0.0);
+        dx.hypot(dy)
+    }
+}
+
+impl From<ElementRect> for Bounds {
+    fn from(rect: ElementRect) -> Self {
+        Self {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+/// Where a candidate element must be relative to an anchor element.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+    Above,
+    Below,
+    LeftOf,
+    RightOf,
+    /// Within the given number of pixels.
+    Near(f64),
+}
+
+impl Relation {
+    fn holds(self, anchor: &Bounds, candidate: &Bounds) -> bool {
+        match self {
+            Self::Above => candidate.bottom() <= anchor.y,
+            Self::Below => candidate.y >= anchor.bottom(),
+            Self::LeftOf => candidate.right() <= anchor.x,
+            Self::RightOf => candidate.x >= anchor.right(),
+            Self::Near(distance) => anchor.gap(candidate) <= distance,
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Above => write!(f, "above the anchor"),
+            Self::Below => write!(f, "below the anchor"),
+            Self::LeftOf => write!(f, "left of the anchor"),
+            Self::RightOf => write!(f, "right of the anchor"),
+            Self::Near(distance) => write!(f, "within {distance}px of the anchor"),
+        }
+    }
+}
+
+/// Filter `candidates` to those in the given relation to `anchor`, returning
+/// their indices ordered from closest to furthest from the anchor.
+pub fn rank(anchor: &Bounds, candidates: &[Bounds], relation: Relation) -> Vec<usize> {
+    let (ax, ay) = anchor.centre();
+    let mut matching: Vec<(usize, f64)> = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, candidate)| relation.holds(anchor, candidate))
+        .map(|(i, candidate)| {
+            let (cx, cy) = candidate.centre();
+            (i, (cx - ax).hypot(cy - ay))
+        })
+        .collect();
+    matching.sort_by(|a, b| a.1.total_cmp(&b.1));
+    matching.into_iter().map(|(i, _)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rank, Bounds, Relation};
+
+    fn bounds(x: f64, y: f64, width: f64, height: f64) -> Bounds {
+        Bounds {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_rank_directions() {
+        // A label at (100, 100) with inputs around it
+        let anchor = bounds(100.0, 100.0, 50.0, 20.0);
+        let candidates = [
+            bounds(160.0, 100.0, 100.0, 20.0), // right
+            bounds(100.0, 130.0, 100.0, 20.0), // below
+            bounds(100.0, 60.0, 100.0, 20.0),  // above
+            bounds(0.0, 100.0, 80.0, 20.0),    // left
+            bounds(400.0, 100.0, 100.0, 20.0), // far right
+        ];
+        assert_eq!(rank(&anchor, &candidates, Relation::RightOf), vec![0, 4]);
+        assert_eq!(rank(&anchor, &candidates, Relation::Below), vec![1]);
+        assert_eq!(rank(&anchor, &candidates, Relation::Above), vec![2]);
+        assert_eq!(rank(&anchor, &candidates, Relation::LeftOf), vec![3]);
+        assert_eq!(
+            rank(&anchor, &candidates, Relation::Near(20.0)),
+            vec![1, 2, 0, 3]
+        );
+    }
+
+    #[test]
+    fn test_rank_overlapping() {
+        let anchor = bounds(0.0, 0.0, 100.0, 100.0);
+        let inside = [bounds(10.0, 10.0, 10.0, 10.0)];
+        assert!(rank(&anchor, &inside, Relation::Above).is_empty());
+        assert!(rank(&anchor, &inside, Relation::Below).is_empty());
+        assert_eq!(rank(&anchor, &inside, Relation::Near(0.0)), vec![0]);
+    }
+}
@@ -0,0 +1,179 @@
+// Helpers for inspecting the accessibility of elements from JavaScript. These
+// approximate the browser's own computations and are used where WebDriver
+// doesn't expose the computed role and accessible name.
+
+function taNormalise(text) {
+  return (text || "").replace(/\s+/g, " ").trim();
+}
+
+function taImplicitRole(el) {
+  var tag = el.tagName.toLowerCase();
+  var type = (el.getAttribute("type") || "text").toLowerCase();
+  switch (tag) {
+    case "a":
+    case "area":
+      return el.hasAttribute("href") ? "link" : "generic";
+    case "article":
+      return "article";
+    case "aside":
+      return "complementary";
+    case "button":
+      return "button";
+    case "dialog":
+      return "dialog";
+    case "footer":
+      return "contentinfo";
+    case "form":
+      return "form";
+    case "h1":
+    case "h2":
+    case "h3":
+    case "h4":
+    case "h5":
+    case "h6":
+      return "heading";
+    case "header":
+      return "banner";
+    case "hr":
+      return "separator";
+    case "img":
+      return el.getAttribute("alt") === "" ? "presentation" : "img";
+    case "input":
+      switch (type) {
+        case "button":
+        case "image":
+        case "reset":
+        case "submit":
+          return "button";
+        case "checkbox":
+          return "checkbox";
+        case "radio":
+          return "radio";
+        case "range":
+          return "slider";
+        case "number":
+          return "spinbutton";
+        case "search":
+          return el.hasAttribute("list") ? "combobox" : "searchbox";
+        case "email":
+        case "tel":
+        case "text":
+        case "url":
+          return el.hasAttribute("list") ? "combobox" : "textbox";
+        default:
+          return "";
+      }
+    case "li":
+      return "listitem";
+    case "main":
+      return "main";
+    case "nav":
+      return "navigation";
+    case "ol":
+    case "ul":
+      return "list";
+    case "option":
+      return "option";
+    case "progress":
+      return "progressbar";
+    case "section":
+      // Only names given by the author count here, as taName() depends on
+      // the role and would recurse.
+      return taLabel(el) || taNormalise(el.getAttribute("title"))
+        ? "region"
+        : "generic";
+    case "select":
+      return el.multiple || el.size > 1 ? "listbox" : "combobox";
+    case "table":
+      return "table";
+    case "td":
+      return "cell";
+    case "textarea":
+      return "textbox";
+    case "th":
+      return el.getAttribute("scope") === "row" ? "rowheader" : "columnheader";
+    case "tr":
+      return "row";
+    default:
+      return "";
+  }
+}
+
+function taRole(el) {
+  var explicit = taNormalise(el.getAttribute("role")).split(" ")[0];
+  return explicit || taImplicitRole(el);
+}
+
+function taText(el) {
+  return taNormalise(el.innerText !== undefined ? el.innerText : el.textContent);
+}
+
+function taLabelledBy(el) {
+  var ids = taNormalise(el.getAttribute("aria-labelledby"));
+  if (!ids) {
+    return "";
+  }
+  return taNormalise(
+    ids
+      .split(" ")
+      .map(function (id) {
+        var label = document.getElementById(id);
+        return label ? taText(label) : "";
+      })
+      .join(" ")
+  );
+}
+
+// The text of any labels associated with a form control.
+function taLabel(el) {
+  var label = taLabelledBy(el) || taNormalise(el.getAttribute("aria-label"));
+  if (label) {
+    return label;
+  }
+  if (el.labels && el.labels.length) {
+    return taNormalise(
+      Array.prototype.map
+        .call(el.labels, function (l) {
+          return taText(l);
+        })
+        .join(" ")
+    );
+  }
+  return "";
+}
+
+var TA_NAME_FROM_CONTENT = [
+  "button",
+  "cell",
+  "checkbox",
+  "columnheader",
+  "heading",
+  "link",
+  "menuitem",
+  "option",
+  "radio",
+  "rowheader",
+  "switch",
+  "tab",
+  "treeitem",
+];
+
+function taName(el) {
+  var name = taLabel(el);
+  if (name) {
+    return name;
+  }
+  var tag = el.tagName.toLowerCase();
+  var type = (el.getAttribute("type") || "").toLowerCase();
+  if (tag === "img" || tag === "area" || (tag === "input" && type === "image")) {
+    name = taNormalise(el.getAttribute("alt"));
+  } else if (tag === "input" && ["button", "submit", "reset"].indexOf(type) !== -1) {
+    name = taNormalise(el.value) || (type === "reset" ? "Reset" : type === "submit" ? "Submit" : "");
+  } else if (tag === "fieldset" || tag === "table" || tag === "figure") {
+    var caption = el.querySelector(tag === "fieldset" ? "legend" : tag === "table" ? "caption" : "figcaption");
+    name = caption ? taText(caption) : "";
+  } else if (TA_NAME_FROM_CONTENT.indexOf(taRole(el)) !== -1) {
+    name = taText(el);
+  }
+  return name || taNormalise(el.getAttribute("title")) || taNormalise(el.getAttribute("placeholder"));
+}
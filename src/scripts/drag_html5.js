@@ -0,0 +1,36 @@
+// Drag one element onto another by dispatching HTML5 drag and drop events
+// directly, for pages where pointer actions don't trigger `dragstart`.
+//
+// arguments[0]: the element to drag
+// arguments[1]: the element to drop onto
+//
+// Returns true if the drop was accepted by the target.
+var source = arguments[0];
+var target = arguments[1];
+var dataTransfer = new DataTransfer();
+
+function fire(el, type) {
+  var rect = el.getBoundingClientRect();
+  var event = new DragEvent(type, {
+    bubbles: true,
+    cancelable: true,
+    composed: true,
+    dataTransfer: dataTransfer,
+    clientX: rect.left + rect.width / 2,
+    clientY: rect.top + rect.height / 2,
+  });
+  el.dispatchEvent(event);
+  return event;
+}
+
+fire(source, "dragstart");
+fire(source, "drag");
+fire(target, "dragenter");
+// As in a real drag, the target only receives the drop if it cancelled
+// `dragover` to show that it accepts it.
+var accepted = fire(target, "dragover").defaultPrevented;
+if (accepted) {
+  fire(target, "drop");
+}
+fire(source, "dragend");
+return accepted;
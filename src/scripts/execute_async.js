@@ -0,0 +1,22 @@
+// Run a script asynchronously. The script is given its arguments followed by
+// a callback to call with its result, or it may return a Promise, or any
+// other value, which is used as the result.
+//
+// Errors are reported as an object with the single key "ta-error", as the
+// script can't throw once it has started waiting.
+var done = arguments[arguments.length - 1];
+function taFail(e) {
+  done({ "ta-error": e && e.message ? e.message : String(e) });
+}
+try {
+  var result = function () {
+    /* SCRIPT */
+  }.apply(this, arguments);
+  if (result && typeof result.then === "function") {
+    result.then(done, taFail);
+  } else if (result !== undefined) {
+    done(result);
+  }
+} catch (e) {
+  taFail(e);
+}
@@ -0,0 +1,16 @@
+// Extract the text of a table. Requires table.js.
+//
+// arguments[0]: the table, or an element with the ARIA role "table", "grid"
+//               or "treegrid"
+//
+// Returns [headers, rows], where rows is an array of the text of each cell in
+// each body row.
+var grid = taTableGrid(arguments[0]);
+return [
+  grid.headers,
+  grid.rows.map(function (row) {
+    return row.cells.map(function (cell) {
+      return cell ? taText(cell) : "";
+    });
+  }),
+];
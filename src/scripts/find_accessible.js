@@ -0,0 +1,43 @@
+// Find elements by accessibility information.
+//
+// arguments[0]: the kind of query, one of "role", "label", "placeholder",
+//               "alt" or "title"
+// arguments[1]: the role to match, for "role" queries
+// arguments[2]: the text to match, ignoring differences in whitespace
+//
+// Returns an array of [element, role, accessible name] for each candidate in
+// document order. For "role" queries, the name is not filtered here so that
+// the caller can compare it with the browser's computed name.
+var kind = arguments[0];
+var role = arguments[1];
+var text = taNormalise(arguments[2]);
+
+var found = [];
+var all = document.querySelectorAll("*");
+for (var i = 0; i < all.length; i++) {
+  var el = all[i];
+  var matches;
+  switch (kind) {
+    case "role":
+      matches = taRole(el) === role;
+      break;
+    case "label":
+      matches = taLabel(el) === text;
+      break;
+    case "placeholder":
+      matches = taNormalise(el.getAttribute("placeholder")) === text;
+      break;
+    case "alt":
+      matches = taNormalise(el.getAttribute("alt")) === text;
+      break;
+    case "title":
+      matches = taNormalise(el.getAttribute("title")) === text;
+      break;
+    default:
+      throw new Error("Unknown accessibility query: " + kind);
+  }
+  if (matches) {
+    found.push([el, taRole(el), taName(el)]);
+  }
+}
+return found;
@@ -0,0 +1,37 @@
+// Find elements by their visible text.
+//
+// arguments[0]: the text to match, ignoring differences in whitespace
+// arguments[1]: how to match the text, one of "equals", "contains" or "regex"
+// arguments[2]: a tag name to restrict the search to, or an empty string
+//
+// Returns the innermost matching elements in document order.
+var text = arguments[0].replace(/\s+/g, " ").trim();
+var mode = arguments[1];
+var tag = arguments[2] || "*";
+var pattern = mode === "regex" ? new RegExp(arguments[0]) : null;
+
+function matches(el) {
+  var visible = (el.innerText || "").replace(/\s+/g, " ").trim();
+  switch (mode) {
+    case "equals":
+      return visible === text;
+    case "contains":
+      return visible.indexOf(text) !== -1;
+    case "regex":
+      return pattern.test(visible);
+  }
+  return false;
+}
+
+var found = [];
+var all = document.body ? document.body.querySelectorAll(tag) : [];
+for (var i = 0; i < all.length; i++) {
+  if (matches(all[i])) {
+    found.push(all[i]);
+  }
+}
+return found.filter(function (el) {
+  return !found.some(function (other) {
+    return other !== el && el.contains(other);
+  });
+});
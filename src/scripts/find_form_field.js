@@ -0,0 +1,65 @@
+// Find a field of a form by its name, id or label.
+//
+// arguments[0]: the form, or another element containing the fields
+// arguments[1]: the name, id or label of the field
+// arguments[2]: the value to be filled in, used to choose between radio
+//               buttons which share a name
+//
+// Returns [element, kind], where kind is one of "select", "checkbox", "radio"
+// or "text", or [null, ""] if no field matches. For a group of radio buttons,
+// the element is the button whose value or label matches.
+var form = arguments[0];
+var key = taNormalise(arguments[1]);
+var value = taNormalise(arguments[2]);
+
+var fields = Array.prototype.slice.call(
+  form.elements || form.querySelectorAll("input, select, textarea")
+);
+fields = fields.filter(function (el) {
+  var type = (el.getAttribute("type") || "").toLowerCase();
+  return (
+    ["INPUT", "SELECT", "TEXTAREA"].indexOf(el.tagName) !== -1 &&
+    ["button", "hidden", "image", "reset", "submit"].indexOf(type) === -1
+  );
+});
+
+function kindOf(el) {
+  if (el.tagName === "SELECT") {
+    return "select";
+  }
+  var type = (el.getAttribute("type") || "").toLowerCase();
+  if (type === "checkbox" || type === "radio") {
+    return type;
+  }
+  return "text";
+}
+
+var matches = fields.filter(function (el) {
+  return el.getAttribute("name") === key;
+});
+if (!matches.length) {
+  matches = fields.filter(function (el) {
+    return el.id === key;
+  });
+}
+if (!matches.length) {
+  matches = fields.filter(function (el) {
+    return taLabel(el) === key;
+  });
+}
+if (!matches.length) {
+  return [null, ""];
+}
+
+var radios = matches.filter(function (el) {
+  return kindOf(el) === "radio";
+});
+if (radios.length > 1) {
+  for (var i = 0; i < radios.length; i++) {
+    if (radios[i].value === value || taLabel(radios[i]) === value) {
+      return [radios[i], "radio"];
+    }
+  }
+  return [null, ""];
+}
+return [matches[0], kindOf(matches[0])];
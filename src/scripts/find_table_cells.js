@@ -0,0 +1,27 @@
+// Find the rows of a table with a given value in a column, or the cells of
+// those rows in another column. Requires table.js.
+//
+// arguments[0]: the table, or an element with the ARIA role "table", "grid"
+//               or "treegrid"
+// arguments[1]: the index of the column to match
+// arguments[2]: the value to match, ignoring differences in whitespace
+// arguments[3]: the index of the column to return cells from, or null to
+//               return the rows
+//
+// Returns an array of matching rows or cells in document order.
+var grid = taTableGrid(arguments[0]);
+var matchColumn = arguments[1];
+var value = taNormalise(arguments[2]);
+var resultColumn = arguments[3];
+
+var found = [];
+grid.rows.forEach(function (row) {
+  var cell = row.cells[matchColumn];
+  if (cell && taText(cell) === value) {
+    var result = resultColumn === null ? row.row : row.cells[resultColumn];
+    if (result && found.indexOf(result) === -1) {
+      found.push(result);
+    }
+  }
+});
+return found;
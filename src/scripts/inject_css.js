@@ -0,0 +1,23 @@
+// Add a stylesheet to the document as early as possible, waiting for the
+// document element to exist if necessary.
+(function () {
+  var css = /* CSS */;
+  function add() {
+    var parent = document.head || document.documentElement;
+    if (!parent) {
+      return false;
+    }
+    var style = document.createElement("style");
+    style.setAttribute("data-testangel", "");
+    style.textContent = css;
+    parent.appendChild(style);
+    return true;
+  }
+  if (!add()) {
+    new MutationObserver(function (_, observer) {
+      if (add()) {
+        observer.disconnect();
+      }
+    }).observe(document, { childList: true });
+  }
+})();
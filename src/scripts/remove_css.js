@@ -0,0 +1,9 @@
+// Remove stylesheets added by inject_css.js from the document.
+//
+// arguments[0]: the CSS of the stylesheets to remove
+var css = arguments[0];
+document.querySelectorAll("style[data-testangel]").forEach(function (style) {
+  if (style.textContent === css) {
+    style.remove();
+  }
+});
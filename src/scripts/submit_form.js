@@ -0,0 +1,19 @@
+// Submit the form containing an element, as if by the user, so that the
+// form's validation and submit event handlers run.
+//
+// arguments[0]: the form, a field or submit button within it
+//
+// Returns false if the element isn't in a form.
+var el = arguments[0];
+var form = el.tagName === "FORM" ? el : el.form || el.closest("form");
+if (!form) {
+  return false;
+}
+var submitter =
+  el !== form && (el.type === "submit" || el.type === "image") ? el : null;
+if (form.requestSubmit) {
+  form.requestSubmit(submitter);
+} else {
+  form.submit();
+}
+return true;
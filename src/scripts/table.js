@@ -0,0 +1,114 @@
+// Helpers for reading tables from JavaScript. Both HTML tables and ARIA
+// tables and grids are understood. Requires accessibility.js.
+
+function taTableRows(table) {
+  if (table.tagName === "TABLE") {
+    return Array.prototype.slice.call(table.rows);
+  }
+  return Array.prototype.filter.call(
+    table.querySelectorAll("[role=row]"),
+    function (row) {
+      return row.closest("[role=table], [role=grid], [role=treegrid]") === table;
+    }
+  );
+}
+
+function taTableCells(row) {
+  if (row.tagName === "TR") {
+    return Array.prototype.slice.call(row.cells);
+  }
+  return Array.prototype.filter.call(
+    row.querySelectorAll(
+      "[role=cell], [role=gridcell], [role=columnheader], [role=rowheader]"
+    ),
+    function (cell) {
+      return cell.closest("[role=row]") === row;
+    }
+  );
+}
+
+function taIsHeaderCell(cell) {
+  var role = cell.getAttribute("role");
+  return role ? role === "columnheader" : cell.tagName === "TH";
+}
+
+function taIsHeaderRow(row) {
+  var cells = taTableCells(row);
+  return cells.length > 0 && cells.every(taIsHeaderCell);
+}
+
+function taSpan(cell, attribute) {
+  var span = parseInt(
+    cell.getAttribute(attribute) || cell.getAttribute("aria-" + attribute),
+    10
+  );
+  return span > 0 ? span : 1;
+}
+
+// Lay a table out as a grid, with cells which span several rows or columns
+// repeated in each position they cover. Header rows are those in a <thead>,
+// or leading rows made up only of header cells.
+//
+// Returns { headers, rows }, where headers is the text of the header for each
+// column and rows is an array of { row, cells } for each body row, with the
+// cell element in each column, or null where a row is short.
+function taTableGrid(table) {
+  var rows = taTableRows(table);
+  var grid = [];
+  for (var r = 0; r < rows.length; r++) {
+    grid[r] = grid[r] || [];
+    var column = 0;
+    var cells = taTableCells(rows[r]);
+    for (var c = 0; c < cells.length; c++) {
+      while (grid[r][column]) {
+        column++;
+      }
+      var rowspan = taSpan(cells[c], "rowspan");
+      var colspan = taSpan(cells[c], "colspan");
+      for (var dr = 0; dr < rowspan && r + dr < rows.length; dr++) {
+        grid[r + dr] = grid[r + dr] || [];
+        for (var dc = 0; dc < colspan; dc++) {
+          grid[r + dr][column + dc] = cells[c];
+        }
+      }
+      column += colspan;
+    }
+  }
+
+  var headerRows = 0;
+  while (
+    headerRows < rows.length &&
+    ((rows[headerRows].parentElement &&
+      rows[headerRows].parentElement.tagName === "THEAD") ||
+      taIsHeaderRow(rows[headerRows]))
+  ) {
+    headerRows++;
+  }
+
+  var columns = 0;
+  for (r = 0; r < grid.length; r++) {
+    columns = Math.max(columns, grid[r].length);
+  }
+
+  var headers = [];
+  for (column = 0; column < columns; column++) {
+    var parts = [];
+    for (r = 0; r < headerRows; r++) {
+      var text = grid[r][column] ? taText(grid[r][column]) : "";
+      if (text && parts.indexOf(text) === -1) {
+        parts.push(text);
+      }
+    }
+    headers.push(parts.join(" "));
+  }
+
+  var body = [];
+  for (r = headerRows; r < rows.length; r++) {
+    var row = [];
+    for (column = 0; column < columns; column++) {
+      row.push(grid[r][column] || null);
+    }
+    body.push({ row: rows[r], cells: row });
+  }
+  return { headers: headers, rows: body };
+}
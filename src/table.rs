@@ -0,0 +1,127 @@
+//! Converting tables extracted from a page into JSON and CSV.
+
+use serde_json::{Map, Value};
+
+/// Make a unique key for each column from its header text. Columns without
+/// header text are numbered, and repeated headers have their position among
+/// the repeats appended, such as `Total (2)`.
+pub fn header_keys(headers: &[String]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::with_capacity(headers.len());
+    for (index, header) in headers.iter().enumerate() {
+        let base = if header.is_empty() {
+            format!("Column {}", index + 1)
+        } else {
+            header.clone()
+        };
+        let mut key = base.clone();
+        let mut repeat = 1;
+        while keys.contains(&key) {
+            repeat += 1;
+            key = format!("{base} ({repeat})");
+        }
+        keys.push(key);
+    }
+    keys
+}
+
+/// Find the column with the key `key`, as given by [`header_keys`], so that
+/// keys from extracted tables can be used to find columns.
+pub fn column_index(headers: &[String], key: &str) -> Option<usize> {
+    let key = crate::utils::normalise_whitespace(key);
+    header_keys(headers).iter().position(|k| *k == key)
+}
+
+/// Convert the rows of a table into a JSON array of objects keyed by header.
+pub fn to_json(headers: &[String], rows: &[Vec<String>]) -> Value {
+    let keys = header_keys(headers);
+    Value::Array(
+        rows.iter()
+            .map(|row| {
+                Value::Object(
+                    keys.iter()
+                        .zip(row)
+                        .map(|(key, cell)| (key.clone(), Value::String(cell.clone())))
+                        .collect::<Map<_, _>>(),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Quote a CSV field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Convert a table into CSV, with a header line.
+pub fn to_csv(headers: &[String], rows: &[Vec<String>]) -> String {
+    std::iter::once(header_keys(headers))
+        .chain(rows.iter().cloned())
+        .map(|row| {
+            row.iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .map(|line| line + "\r\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{column_index, header_keys, to_csv, to_json};
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_header_keys() {
+        assert_eq!(
+            header_keys(&strings(&["Name", "", "Total", "Total", "Total"])),
+            strings(&["Name", "Column 2", "Total", "Total (2)", "Total (3)"])
+        );
+    }
+
+    #[test]
+    fn test_column_index() {
+        let headers = strings(&["Name", "", "Total", "Total"]);
+        assert_eq!(column_index(&headers, "Name"), Some(0));
+        assert_eq!(column_index(&headers, "Column 2"), Some(1));
+        assert_eq!(column_index(&headers, " Total "), Some(2));
+        assert_eq!(column_index(&headers, "Total (2)"), Some(3));
+        assert_eq!(column_index(&headers, "Amount"), None);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let headers = strings(&["Invoice", "Amount"]);
+        let rows = vec![strings(&["1042", "£10"]), strings(&["1043", "£20"])];
+        assert_eq!(
+            to_json(&headers, &rows),
+            json!([
+                { "Invoice": "1042", "Amount": "£10" },
+                { "Invoice": "1043", "Amount": "£20" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let headers = strings(&["Name", "Notes"]);
+        let rows = vec![
+            strings(&["Ann", "says \"hi\", twice"]),
+            strings(&["Bob", ""]),
+        ];
+        assert_eq!(
+            to_csv(&headers, &rows),
+            "Name,Notes\r\nAnn,\"says \"\"hi\"\", twice\"\r\nBob,\r\n"
+        );
+    }
+}
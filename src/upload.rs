@@ -0,0 +1,50 @@
+//! Packaging files to be uploaded to a remote `WebDriver`, which expects each
+//! file as a zip archive.
+
+use std::io::{Cursor, Write};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Build a zip archive containing a single, uncompressed file.
+pub fn zip_single_file(name: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    let fail = |e: &dyn std::fmt::Display| format!("Failed to package {name:?} for upload: {e}");
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(u32::try_from(data.len()).is_err());
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::with_capacity(data.len() + 256)));
+    zip.start_file(name, options).map_err(|e| fail(&e))?;
+    zip.write_all(data).map_err(|e| fail(&e))?;
+    Ok(zip.finish().map_err(|e| fail(&e))?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::zip_single_file;
+
+    /// Find `needle` in `haystack`.
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    }
+
+    #[test]
+    fn test_zip_single_file() {
+        let zip = zip_single_file("hello.txt", b"Hello, world!").unwrap();
+        assert_eq!(&zip[0..4], b"PK\x03\x04");
+        // Stored, so the contents appear as they are
+        assert_eq!(&zip[8..10], &[0, 0]);
+        assert!(find(&zip, b"hello.txt").is_some());
+        assert!(find(&zip, b"Hello, world!").is_some());
+        assert_eq!(&zip[zip.len() - 22..zip.len() - 18], b"PK\x05\x06");
+    }
+
+    #[test]
+    fn test_zip_utf8_name() {
+        let zip = zip_single_file("résumé.txt", b"CV").unwrap();
+        let flags = u16::from_le_bytes([zip[6], zip[7]]);
+        assert_ne!(flags & (1 << 11), 0, "the UTF-8 file name flag isn't set");
+        assert!(find(&zip, "résumé.txt".as_bytes()).is_some());
+    }
+}
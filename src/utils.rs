@@ -1,17 +1,363 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use serde_json::{json, Value};
 use thirtyfour::{error::WebDriverResult, session::handle::SessionHandle, WebElement};
 
-pub fn serialise_elem(elem: &WebElement) -> WebDriverResult<String> {
-    Ok(elem.to_json()?.to_string())
-}
+use crate::EngineError;
+
+/// The key identifying an element in its `WebDriver` JSON.
+const ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f713b96ec9a";
 
+/// Deserialise an element from its `WebDriver` JSON, as was previously used for
+/// element handles. If the JSON also records a frame context, check that it
+/// matches the current frame.
 pub fn deserialise_elem<S: AsRef<str>>(
     handle: &Arc<SessionHandle>,
     s: S,
-) -> Result<WebElement, String> {
+    current_frame: &[String],
+) -> Result<WebElement, EngineError> {
     let s = s.as_ref();
-    let json_elem =
-        serde_json::from_str(s).map_err(|e| format!("Invalid element parameter: {e}"))?;
-    WebElement::from_json(json_elem, handle.clone()).map_err(|e| format!("Invalid element: {e}"))
+    let mut json_elem: Value =
+        serde_json::from_str(s).map_err(|e| EngineError::InvalidElement(e.to_string()))?;
+    if let Some(frame) = json_elem.get("frame") {
+        let frame: Vec<String> = serde_json::from_value(frame.clone())
+            .map_err(|e| EngineError::InvalidElement(e.to_string()))?;
+        if frame != current_frame {
+            return Err(EngineError::WrongFrame {
+                element: describe_frame(&frame),
+                current: describe_frame(current_frame),
+            });
+        }
+        json_elem = json_elem["element"].take();
+    }
+    WebElement::from_json(json_elem, handle.clone())
+        .map_err(|e| EngineError::InvalidElement(e.to_string()))
+}
+
+/// Describe the options of a dropdown as a JSON array of objects with `text`,
+/// `value` and `selected` properties.
+pub async fn describe_options(options: &[WebElement]) -> WebDriverResult<Value> {
+    let mut described = Vec::with_capacity(options.len());
+    for option in options {
+        described.push(json!({
+            "text": option.text().await?,
+            "value": option.value().await?.unwrap_or_default(),
+            "selected": option.is_selected().await?,
+        }));
+    }
+    Ok(Value::Array(described))
+}
+
+/// Work out the role and accessible name of an element using JavaScript, for
+/// browsers which don't provide them through `WebDriver`.
+pub async fn accessibility_fallback(elem: &WebElement) -> WebDriverResult<(String, String)> {
+    let script = concat!(
+        include_str!("scripts/accessibility.js"),
+        "return [taRole(arguments[0]), taName(arguments[0])];",
+    );
+    let ret = elem.handle.execute(script, vec![elem.to_json()?]).await?;
+    ret.convert()
+}
+
+/// Returns true if `value` is the `WebDriver` JSON of an element.
+pub fn is_element(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|object| object.len() == 1 && object.contains_key(ELEMENT_KEY))
+}
+
+/// Rebuild a JSON value, replacing any part of it for which `f` returns a
+/// replacement. Arrays and objects which aren't replaced are searched within.
+pub fn map_json<E, F>(value: Value, f: &mut F) -> Result<Value, E>
+where
+    F: FnMut(&Value) -> Result<Option<Value>, E>,
+{
+    if let Some(replacement) = f(&value)? {
+        return Ok(replacement);
+    }
+    Ok(match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| map_json(item, f))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, item)| Ok((key, map_json(item, f)?)))
+                .collect::<Result<_, _>>()?,
+        ),
+        other => other,
+    })
+}
+
+/// Round a measurement in pixels to the nearest whole pixel.
+#[allow(clippy::cast_possible_truncation)]
+pub fn round_to_i32(pixels: f64) -> i32 {
+    // Casting saturates at the bounds of i32, which no page will reach
+    pixels.round() as i32
+}
+
+/// Describe a frame context in a human readable form.
+pub fn describe_frame(frame: &[String]) -> String {
+    if frame.is_empty() {
+        "the top-level document".to_string()
+    } else {
+        frame.join(" > ")
+    }
+}
+
+/// Quote a string for use as a CSS string literal, for example in an
+/// attribute selector.
+pub fn css_string<S: AsRef<str>>(s: S) -> String {
+    let mut quoted = String::from("\"");
+    for ch in s.as_ref().chars() {
+        match ch {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(ch);
+            }
+            '\n' => quoted.push_str("\\a "),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Match `text` against a simple wildcard `pattern`, where `*` matches any
+/// sequence of characters and `?` matches any single character.
+pub fn wildcard_match<P: AsRef<str>, T: AsRef<str>>(pattern: P, text: T) -> bool {
+    let pattern: Vec<char> = pattern.as_ref().chars().collect();
+    let text: Vec<char> = text.as_ref().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '*')
+}
+
+/// Collapse runs of whitespace into single spaces and trim the ends, as
+/// browsers do when rendering text.
+pub fn normalise_whitespace<S: AsRef<str>>(s: S) -> String {
+    s.as_ref().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Interpret a JSON value given for a checkbox as checked or unchecked.
+pub fn truthy(value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        Value::Null => Ok(false),
+        Value::Number(n) => Ok(n.as_f64() != Some(0.0)),
+        Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" | "checked" => Ok(true),
+            "false" | "no" | "off" | "0" | "unchecked" | "" => Ok(false),
+            _ => Err(format!("{s:?} isn't a valid checkbox state.")),
+        },
+        _ => Err(format!("{value} isn't a valid checkbox state.")),
+    }
+}
+
+/// Repeatedly call `f` every `interval` until it returns a value or `timeout`
+/// has elapsed.
+pub fn poll<T, E, F>(timeout: Duration, interval: Duration, mut f: F) -> Result<Option<T>, E>
+where
+    F: FnMut() -> Result<Option<T>, E>,
+{
+    let started = Instant::now();
+    loop {
+        if let Some(value) = f()? {
+            return Ok(Some(value));
+        }
+        if started.elapsed() > timeout {
+            return Ok(None);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// A source of randomly varying delays, for example to imitate a person
+/// typing. This doesn't need to be a good random number generator, so a
+/// simple xorshift generator seeded from the clock is used.
+pub struct Jitter(u64);
+
+impl Jitter {
+    pub fn from_clock() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        Self::with_seed(u64::from(nanos))
+    }
+
+    fn with_seed(seed: u64) -> Self {
+        // Xorshift gets stuck at zero
+        Self(seed | 1)
+    }
+
+    /// Get a delay of `base` milliseconds, varied randomly by up to `jitter`
+    /// milliseconds either way.
+    pub fn delay(&mut self, base: u64, jitter: u64) -> Duration {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        let offset = self.0 % (2 * jitter + 1);
+        Duration::from_millis((base + offset).saturating_sub(jitter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{
+        css_string, describe_frame, is_element, map_json, normalise_whitespace, truthy,
+        wildcard_match, Jitter,
+    };
+
+    #[test]
+    fn test_css_string() {
+        assert_eq!(css_string("payment"), r#""payment""#);
+        assert_eq!(css_string(r#"a "b" c"#), r#""a \"b\" c""#);
+        assert_eq!(css_string(r"a\b"), r#""a\\b""#);
+        assert_eq!(css_string("a\nb"), r#""a\a b""#);
+    }
+
+    #[test]
+    fn test_describe_frame() {
+        assert_eq!(describe_frame(&[]), "the top-level document");
+        assert_eq!(
+            describe_frame(&["iframe #outer".to_string(), "iframe #inner".to_string()]),
+            "iframe #outer > iframe #inner"
+        );
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("", ""));
+        assert!(wildcard_match("Checkout", "Checkout"));
+        assert!(!wildcard_match("Checkout", "Checkout - Shop"));
+        assert!(wildcard_match("Checkout*", "Checkout - Shop"));
+        assert!(wildcard_match(
+            "*://example.com/*",
+            "https://example.com/pay?id=1"
+        ));
+        assert!(!wildcard_match("*://example.com/*", "https://example.org/"));
+        assert!(wildcard_match("page?.html", "page2.html"));
+        assert!(!wildcard_match("page?.html", "page.html"));
+        assert!(wildcard_match("*a*b", "xxaxxab"));
+    }
+
+    #[test]
+    fn test_normalise_whitespace() {
+        assert_eq!(normalise_whitespace(""), "");
+        assert_eq!(normalise_whitespace("  Email\n  address "), "Email address");
+        assert_eq!(normalise_whitespace("Submit"), "Submit");
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut jitter = Jitter::with_seed(42);
+        for _ in 0..1000 {
+            let delay = jitter.delay(100, 30);
+            assert!(delay >= Duration::from_millis(70) && delay <= Duration::from_millis(130));
+        }
+        assert_eq!(jitter.delay(100, 0), Duration::from_millis(100));
+        assert!(jitter.delay(10, 50) <= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_truthy() {
+        assert_eq!(truthy(&json!(true)), Ok(true));
+        assert_eq!(truthy(&json!("Yes")), Ok(true));
+        assert_eq!(truthy(&json!(1)), Ok(true));
+        assert_eq!(truthy(&json!("off")), Ok(false));
+        assert_eq!(truthy(&json!(null)), Ok(false));
+        assert!(truthy(&json!("maybe")).is_err());
+        assert!(truthy(&json!([])).is_err());
+    }
+
+    #[test]
+    fn test_map_json() {
+        let elem = json!({ "element-6066-11e4-a52e-4f713b96ec9a": "abc" });
+        assert!(is_element(&elem));
+        assert!(!is_element(
+            &json!({ "element-6066-11e4-a52e-4f713b96ec9a": "abc", "x": 1 })
+        ));
+        assert!(!is_element(&json!("abc")));
+
+        let value = json!([elem.clone(), { "rows": [elem, 1, "text"] }]);
+        let mut count = 0;
+        let mapped = map_json(value, &mut |value| {
+            if is_element(value) {
+                count += 1;
+                Ok::<_, ()>(Some(json!(format!("el-{count}"))))
+            } else {
+                Ok(None)
+            }
+        });
+        assert_eq!(count, 2);
+        assert_eq!(mapped, Ok(json!(["el-1", { "rows": ["el-2", 1, "text"] }])));
+    }
+
+    /// Runs `scripts/accessibility.js`, as used by `accessibility_fallback`, in
+    /// Node.js to check that roles and names of `<section>` elements can be
+    /// computed. Node.js must be installed.
+    #[test]
+    fn test_accessibility_script_section() {
+        let harness = r#"
+            function section(attributes) {
+                return {
+                    tagName: "SECTION",
+                    innerText: "Content",
+                    getAttribute: function (name) {
+                        return name in attributes ? attributes[name] : null;
+                    },
+                    querySelector: function () {
+                        return null;
+                    },
+                };
+            }
+            console.log(JSON.stringify([
+                taRole(section({})),
+                taName(section({})),
+                taRole(section({ "aria-label": "Results" })),
+                taName(section({ "aria-label": "Results" })),
+            ]));
+        "#;
+        let script = format!("{}\n{harness}", include_str!("scripts/accessibility.js"));
+        let output = std::process::Command::new("node")
+            .args(["-e", &script])
+            .output()
+            .expect("Node.js must be installed to test the browser scripts");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            r#"["generic","","region","Results"]"#
+        );
+    }
 }